@@ -0,0 +1,273 @@
+//! Compares `stern` against other string interning and small-string crates.
+//!
+//! Run with `just bench`, or `just bench-sizes` to run the suite once for
+//! each `atom_size_*` feature. Results are saved as criterion baselines under
+//! `target/criterion`, so they can be compared across commits.
+
+use core::hash::{Hash, Hasher};
+
+use compact_str::CompactString;
+use criterion::{
+    black_box, criterion_group, criterion_main, BenchmarkGroup, Criterion, Throughput,
+};
+use lasso::Rodeo;
+use rustc_hash::FxHasher;
+use smol_str::SmolStr;
+use stern::{Atom, AtomStore, LocalAtom, LocalAtomStore};
+use ustr::Ustr;
+
+type DefaultAtom = string_cache::DefaultAtom;
+
+mod common;
+
+fn group<'c>(
+    c: &'c mut Criterion,
+    name: &str,
+    inputs: &[&str],
+) -> BenchmarkGroup<'c, criterion::measurement::WallTime> {
+    let mut group = c.benchmark_group(name);
+    group.throughput(Throughput::Elements(inputs.len() as u64));
+    group
+}
+
+/// Intern every identifier into an interner that has already seen them.
+fn intern_warm(c: &mut Criterion) {
+    let inputs = common::identifiers();
+    let mut group = group(c, "intern_warm", &inputs);
+
+    group.bench_function("stern::Atom", |b| {
+        b.iter(|| {
+            for s in &inputs {
+                black_box(Atom::new(s));
+            }
+        });
+    });
+    group.bench_function("stern::AtomStore", |b| {
+        let mut store = AtomStore::default();
+        let _warm: Vec<_> = inputs.iter().map(|s| store.atom(s)).collect();
+        b.iter(|| {
+            for s in &inputs {
+                black_box(store.atom(s));
+            }
+        });
+    });
+    group.bench_function("stern::LocalAtomStore", |b| {
+        let mut store = LocalAtomStore::new();
+        let _warm: Vec<_> = inputs.iter().map(|s| store.atom(s)).collect();
+        b.iter(|| {
+            for s in &inputs {
+                black_box(store.atom(s));
+            }
+        });
+    });
+    group.bench_function("string_cache", |b| {
+        b.iter(|| {
+            for s in &inputs {
+                black_box(DefaultAtom::from(*s));
+            }
+        });
+    });
+    group.bench_function("lasso", |b| {
+        let mut rodeo = Rodeo::default();
+        b.iter(|| {
+            for s in &inputs {
+                black_box(rodeo.get_or_intern(s));
+            }
+        });
+    });
+    group.bench_function("ustr", |b| {
+        b.iter(|| {
+            for s in &inputs {
+                black_box(Ustr::from(s));
+            }
+        });
+    });
+    group.bench_function("smol_str", |b| {
+        b.iter(|| {
+            for s in &inputs {
+                black_box(SmolStr::new(s));
+            }
+        });
+    });
+    group.bench_function("compact_str", |b| {
+        b.iter(|| {
+            for s in &inputs {
+                black_box(CompactString::new(s));
+            }
+        });
+    });
+    group.finish();
+}
+
+/// Intern every identifier into a fresh interner. Only covers interners that
+/// can be created and dropped on demand.
+fn intern_cold(c: &mut Criterion) {
+    let inputs = common::identifiers();
+    let mut group = group(c, "intern_cold", &inputs);
+
+    group.bench_function("stern::AtomStore", |b| {
+        b.iter(|| {
+            let mut store = AtomStore::default();
+            inputs.iter().map(|s| store.atom(s)).collect::<Vec<_>>()
+        });
+    });
+    group.bench_function("lasso", |b| {
+        b.iter(|| {
+            let mut rodeo = Rodeo::default();
+            let keys = inputs
+                .iter()
+                .map(|s| rodeo.get_or_intern(s))
+                .collect::<Vec<_>>();
+            (rodeo, keys)
+        });
+    });
+    group.finish();
+}
+
+/// Intern a list of identifiers too big for the CPU's caches, one at a time
+/// and as one batch.
+fn intern_batch(c: &mut Criterion) {
+    let owned = common::symbol_index(200_000);
+    let inputs: Vec<&str> = owned.iter().map(String::as_str).collect();
+    let mut group = group(c, "intern_batch", &inputs);
+    group.sample_size(20);
+
+    group.bench_function("cold/one_by_one", |b| {
+        b.iter(|| {
+            let mut store = AtomStore::default();
+            inputs.iter().map(|s| store.atom(s)).collect::<Vec<_>>()
+        });
+    });
+    group.bench_function("cold/batch", |b| {
+        b.iter(|| AtomStore::default().intern_batch(&inputs));
+    });
+
+    let mut store = AtomStore::default();
+    store.extend(&inputs);
+    group.bench_function("warm/one_by_one", |b| {
+        b.iter(|| inputs.iter().map(|s| store.atom(s)).collect::<Vec<_>>());
+    });
+    group.bench_function("warm/batch", |b| b.iter(|| store.intern_batch(&inputs)));
+    group.finish();
+}
+
+/// Clone every atom in a list, split by whether `stern` inlines them.
+fn clone(c: &mut Criterion) {
+    for (name, inputs) in [
+        ("clone_inline", common::short_identifiers()),
+        ("clone_heap", common::long_identifiers()),
+    ] {
+        let mut group = group(c, name, &inputs);
+
+        let atoms: Vec<Atom> = inputs.iter().map(Atom::new).collect();
+        group.bench_function("stern::Atom", |b| b.iter(|| black_box(&atoms).clone()));
+
+        let atoms: Vec<LocalAtom> = inputs.iter().map(LocalAtom::new).collect();
+        group.bench_function("stern::LocalAtom", |b| {
+            b.iter(|| black_box(&atoms).clone());
+        });
+
+        let atoms: Vec<DefaultAtom> = inputs.iter().map(|s| DefaultAtom::from(*s)).collect();
+        group.bench_function("string_cache", |b| b.iter(|| black_box(&atoms).clone()));
+
+        let mut rodeo = Rodeo::default();
+        let keys: Vec<_> = inputs.iter().map(|s| rodeo.get_or_intern(s)).collect();
+        group.bench_function("lasso", |b| b.iter(|| black_box(&keys).clone()));
+
+        let atoms: Vec<Ustr> = inputs.iter().map(|s| Ustr::from(s)).collect();
+        group.bench_function("ustr", |b| b.iter(|| black_box(&atoms).clone()));
+
+        let atoms: Vec<SmolStr> = inputs.iter().map(SmolStr::new).collect();
+        group.bench_function("smol_str", |b| b.iter(|| black_box(&atoms).clone()));
+
+        let atoms: Vec<CompactString> = inputs.iter().map(CompactString::new).collect();
+        group.bench_function("compact_str", |b| b.iter(|| black_box(&atoms).clone()));
+
+        group.finish();
+    }
+}
+
+fn hash_all<T: Hash>(values: &[T]) -> u64 {
+    values.iter().fold(0, |acc, value| {
+        let mut hasher = FxHasher::default();
+        value.hash(&mut hasher);
+        acc ^ hasher.finish()
+    })
+}
+
+/// Hash every atom with `FxHasher`.
+fn hash(c: &mut Criterion) {
+    let inputs = common::identifiers();
+    let mut group = group(c, "hash", &inputs);
+
+    let atoms: Vec<Atom> = inputs.iter().map(Atom::new).collect();
+    group.bench_function("stern::Atom", |b| b.iter(|| hash_all(black_box(&atoms))));
+
+    let atoms: Vec<DefaultAtom> = inputs.iter().map(|s| DefaultAtom::from(*s)).collect();
+    group.bench_function("string_cache", |b| b.iter(|| hash_all(black_box(&atoms))));
+
+    let mut rodeo = Rodeo::default();
+    let keys: Vec<_> = inputs.iter().map(|s| rodeo.get_or_intern(s)).collect();
+    group.bench_function("lasso", |b| b.iter(|| hash_all(black_box(&keys))));
+
+    let atoms: Vec<Ustr> = inputs.iter().map(|s| Ustr::from(s)).collect();
+    group.bench_function("ustr", |b| b.iter(|| hash_all(black_box(&atoms))));
+
+    let atoms: Vec<SmolStr> = inputs.iter().map(SmolStr::new).collect();
+    group.bench_function("smol_str", |b| b.iter(|| hash_all(black_box(&atoms))));
+
+    let atoms: Vec<CompactString> = inputs.iter().map(CompactString::new).collect();
+    group.bench_function("compact_str", |b| b.iter(|| hash_all(black_box(&atoms))));
+
+    group.finish();
+}
+
+fn count_repeats<T: PartialEq>(values: &[T]) -> usize {
+    values.windows(2).filter(|pair| pair[0] == pair[1]).count()
+}
+
+/// Compare each atom with its neighbor. Most pairs are unequal.
+fn compare(c: &mut Criterion) {
+    let inputs = common::identifiers();
+    let mut group = group(c, "compare", &inputs);
+
+    let atoms: Vec<Atom> = inputs.iter().map(Atom::new).collect();
+    group.bench_function("stern::Atom", |b| {
+        b.iter(|| count_repeats(black_box(&atoms)));
+    });
+
+    let atoms: Vec<DefaultAtom> = inputs.iter().map(|s| DefaultAtom::from(*s)).collect();
+    group.bench_function("string_cache", |b| {
+        b.iter(|| count_repeats(black_box(&atoms)));
+    });
+
+    let mut rodeo = Rodeo::default();
+    let keys: Vec<_> = inputs.iter().map(|s| rodeo.get_or_intern(s)).collect();
+    group.bench_function("lasso", |b| b.iter(|| count_repeats(black_box(&keys))));
+
+    let atoms: Vec<Ustr> = inputs.iter().map(|s| Ustr::from(s)).collect();
+    group.bench_function("ustr", |b| b.iter(|| count_repeats(black_box(&atoms))));
+
+    let atoms: Vec<SmolStr> = inputs.iter().map(SmolStr::new).collect();
+    group.bench_function("smol_str", |b| {
+        b.iter(|| count_repeats(black_box(&atoms)));
+    });
+
+    let atoms: Vec<CompactString> = inputs.iter().map(CompactString::new).collect();
+    group.bench_function("compact_str", |b| {
+        b.iter(|| count_repeats(black_box(&atoms)));
+    });
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    intern_warm,
+    intern_cold,
+    intern_batch,
+    clone,
+    hash,
+    compare
+);
+criterion_main!(benches);
@@ -0,0 +1,42 @@
+//! Shared benchmark inputs.
+//!
+//! The corpus is rustdoc's search script (MIT OR Apache-2.0), a real-world
+//! JavaScript file with whitespace stripped but identifiers left intact.
+
+/// Every identifier in the corpus, in source order and including repeats.
+pub fn identifiers() -> Vec<&'static str> {
+    const CORPUS: &str = include_str!("../fixtures/rustdoc-search.js");
+
+    let is_ident_char = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '$';
+    CORPUS
+        .split(|c: char| !is_ident_char(c))
+        .filter(|token| token.chars().next().is_some_and(|c| !c.is_ascii_digit()))
+        .collect()
+}
+
+/// Identifiers that fit in an inline [`stern::Atom`].
+pub fn short_identifiers() -> Vec<&'static str> {
+    let max_inline_len = core::mem::size_of::<stern::Atom>() - 1;
+    identifiers()
+        .into_iter()
+        .filter(|s| s.len() <= max_inline_len)
+        .collect()
+}
+
+/// Identifiers too long to be inlined in a [`stern::Atom`].
+pub fn long_identifiers() -> Vec<&'static str> {
+    let max_inline_len = core::mem::size_of::<stern::Atom>() - 1;
+    identifiers()
+        .into_iter()
+        .filter(|s| s.len() > max_inline_len)
+        .collect()
+}
+
+/// A large list of distinct identifiers, like an index of symbols loaded from
+/// a cache: every identifier in the corpus, suffixed with a number.
+pub fn symbol_index(len: usize) -> Vec<String> {
+    let identifiers = identifiers();
+    (0..len)
+        .map(|i| format!("{}_{i}", identifiers[i % identifiers.len()]))
+        .collect()
+}
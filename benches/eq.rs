@@ -0,0 +1,34 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use stern::{Atom, AtomStore};
+
+/// Equality of equal heap atoms from different stores, which can't take the
+/// pointer-equality fast path.
+fn cross_store_eq(c: &mut Criterion) {
+    let mut group = c.benchmark_group("cross_store_eq");
+    for len in [16, 31, 64, 255, 1024] {
+        let text: String = (b'a'..=b'z').cycle().take(len).map(char::from).collect();
+        let mut store1 = AtomStore::default();
+        let mut store2 = AtomStore::default();
+        let a: Atom = store1.atom(&text);
+        let b: Atom = store2.atom(&text);
+
+        group.bench_with_input(
+            BenchmarkId::new("Atom::eq", len),
+            &(&a, &b),
+            |bench, (a, b)| {
+                bench.iter(|| black_box(a) == black_box(b));
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("as_str", len),
+            &(&a, &b),
+            |bench, (a, b)| {
+                bench.iter(|| black_box(a).as_str() == black_box(b).as_str());
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, cross_store_eq);
+criterion_main!(benches);
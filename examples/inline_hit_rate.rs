@@ -0,0 +1,48 @@
+//! How many identifiers in a real JavaScript file get stored inline, for each
+//! atom size.
+//!
+//! ```sh
+//! cargo run --example inline_hit_rate
+//! ```
+//!
+//! The corpus is the same one the benchmarks use: rustdoc's search script.
+
+use std::collections::HashSet;
+
+use stern::{Atom, AtomSize, Bits128, Bits32, Bits64, Packed64};
+
+// Only `identifiers` is used here.
+#[allow(dead_code)]
+#[path = "../benches/common/mod.rs"]
+mod common;
+
+fn main() {
+    let identifiers = common::identifiers();
+    let distinct: HashSet<&str> = identifiers.iter().copied().collect();
+
+    println!(
+        "{} identifiers, {} distinct\n",
+        identifiers.len(),
+        distinct.len()
+    );
+    println!("{:<10} {:>12} {:>12}", "size", "occurrences", "distinct");
+    report::<Bits32>("Bits32", &identifiers, &distinct);
+    report::<Bits64>("Bits64", &identifiers, &distinct);
+    report::<Packed64>("Packed64", &identifiers, &distinct);
+    report::<Bits128>("Bits128", &identifiers, &distinct);
+}
+
+/// Print the share of `identifiers` and `distinct` that are inline.
+#[allow(clippy::cast_precision_loss)]
+fn report<S: AtomSize>(name: &str, identifiers: &[&str], distinct: &HashSet<&str>) {
+    let rate = |inline: usize, total: usize| 100.0 * inline as f64 / total as f64;
+    let inline = |s: &&&str| Atom::<S>::intern(s).is_inline();
+
+    let occurrences = identifiers.iter().filter(inline).count();
+    let unique = distinct.iter().filter(inline).count();
+    println!(
+        "{name:<10} {:>11.1}% {:>11.1}%",
+        rate(occurrences, identifiers.len()),
+        rate(unique, distinct.len())
+    );
+}
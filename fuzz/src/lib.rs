@@ -0,0 +1,147 @@
+//! Fuzzing harnesses for `stern`.
+//!
+//! Each harness drives `stern` with fuzzer-generated input and checks the
+//! results against the reference model in `stern::model`, which the proptest
+//! suite uses too. The harnesses are shared by the `cargo fuzz` targets in
+//! `fuzz_targets/` and by `tests/replay.rs`, which replays the checked-in
+//! corpus under `cargo test` or `cargo miri test`.
+
+use arbitrary::{Arbitrary, Unstructured};
+use stern::model::{self, hash_of, Model};
+use stern::{Atom, AtomBuilder, AtomStore};
+
+#[derive(Debug, Arbitrary)]
+pub enum Op {
+    /// Create an atom using the thread-local global store.
+    New(String),
+    /// Create an atom in one of the [`AtomStore`]s.
+    Intern(u8, String),
+    Concat(Vec<String>),
+    Build(u8, Vec<String>),
+    Substr(usize, usize, usize),
+    Replace(usize, String, String),
+    ToAsciiLowercase(usize),
+    Clone(usize),
+    Drop(usize),
+    /// Drop one of the stores, keeping the atoms it created alive.
+    DropStore(u8),
+    /// Serialize an atom to JSON and deserialize it again.
+    SerdeRoundTrip(usize),
+}
+
+/// Apply `op` to `model`. Operations the model knows are left to it.
+fn apply(model: &mut Model, op: Op) {
+    match op {
+        Op::New(s) => model.apply(model::Op::New(s)),
+        Op::Intern(store, s) => model.apply(model::Op::Intern(usize::from(store), s)),
+        Op::Concat(parts) => {
+            let parts: Vec<&str> = parts.iter().map(String::as_str).collect();
+            model.push(Atom::concat(&parts), parts.concat());
+        }
+        Op::Build(store, parts) => {
+            let mut builder = AtomBuilder::new();
+            for part in &parts {
+                builder.push_str(part);
+            }
+            let expected = parts.concat();
+            assert_eq!(builder.as_str(), expected);
+            assert_eq!(builder.len(), expected.len());
+            let atom = builder.finish_in(model.store(usize::from(store)));
+            model.push(atom, expected);
+        }
+        Op::Substr(i, start, end) => {
+            let Some((atom, s)) = model.get(i) else {
+                return;
+            };
+            let (start, end) = (start % (s.len() + 1), end % (s.len() + 1));
+            let (start, end) = (start.min(end), start.max(end));
+            if !s.is_char_boundary(start) || !s.is_char_boundary(end) {
+                return;
+            }
+            let expected = s[start..end].to_string();
+            let atom = atom.substr(start..end);
+            model.push(atom, expected);
+        }
+        Op::Replace(i, from, to) => {
+            let Some((atom, s)) = model.get(i) else {
+                return;
+            };
+            let expected = s.replace(&from, &to);
+            let atom = atom.replace(&from, &to);
+            model.push(atom, expected);
+        }
+        Op::ToAsciiLowercase(i) => {
+            let Some((atom, s)) = model.get(i) else {
+                return;
+            };
+            let expected = s.to_ascii_lowercase();
+            let atom = atom.to_ascii_lowercase();
+            model.push(atom, expected);
+        }
+        Op::Clone(i) => model.apply(model::Op::Clone(i)),
+        Op::Drop(i) => model.apply(model::Op::Drop(i)),
+        Op::DropStore(store) => model.apply(model::Op::DropStore(usize::from(store))),
+        Op::SerdeRoundTrip(i) => {
+            let Some((atom, s)) = model.get(i) else {
+                return;
+            };
+            let json = serde_json::to_string(atom).unwrap();
+            assert_eq!(json, serde_json::to_string(s).unwrap());
+            let atom: Atom = serde_json::from_str(&json).unwrap();
+            let s = s.clone();
+            model.push(atom, s);
+        }
+    }
+}
+
+/// Apply a sequence of operations to atoms and stores, checking every live
+/// atom against the model after each one.
+pub fn store_ops(data: &[u8]) {
+    let Ok(ops) = Vec::<Op>::arbitrary_take_rest(Unstructured::new(data)) else {
+        return;
+    };
+
+    let mut model = Model::default();
+    for op in ops {
+        apply(&mut model, op);
+        model.check();
+    }
+}
+
+/// Intern strings of every length, so that every padding size of a heap
+/// atom's allocation gets exercised, and compare them across stores.
+pub fn heap_layout(data: &[u8]) {
+    let mut u = Unstructured::new(data);
+    let mut store1 = AtomStore::default();
+    let mut store2 = AtomStore::default();
+
+    // `Unstructured` hands out default values once it runs out of data, so
+    // stop as soon as it's empty instead.
+    while !u.is_empty() {
+        let (Ok(len), Ok(fill)) = (u.int_in_range(0..=512_usize), u.arbitrary::<char>()) else {
+            break;
+        };
+        let s: String = core::iter::repeat(fill).take(len).collect();
+
+        let a = store1.atom(&s);
+        let b = store2.atom(&s);
+        let c = Atom::new(&s);
+        for atom in [&a, &b, &c] {
+            assert_eq!(atom.as_str(), s);
+            assert_eq!(atom.len(), s.len());
+        }
+        assert_eq!(a, b);
+        assert_eq!(b, c);
+        assert_eq!(hash_of(&a), hash_of(&b));
+
+        // Same length, different last character.
+        if let Some(last) = s.chars().last() {
+            let other_last = if last == 'x' { 'y' } else { 'x' };
+            let mut other: String = s.chars().take(s.chars().count() - 1).collect();
+            other.push(other_last);
+            if other.len() == s.len() {
+                assert_ne!(a, store2.atom(&other));
+            }
+        }
+    }
+}
@@ -0,0 +1,37 @@
+//! Replays the fuzzing corpus as regular tests, so it can be run without
+//! libFuzzer, e.g. under Miri:
+//!
+//! ```sh
+//! cargo +nightly miri test --manifest-path fuzz/Cargo.toml
+//! ```
+
+use std::fs;
+use std::path::Path;
+
+fn replay(target: &str, harness: fn(&[u8])) {
+    let corpus = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("corpus")
+        .join(target);
+    let mut entries: Vec<_> = fs::read_dir(&corpus)
+        .unwrap_or_else(|err| panic!("could not read corpus at {}: {err}", corpus.display()))
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    entries.sort();
+    assert!(!entries.is_empty(), "corpus for {target} is empty");
+
+    for path in entries {
+        let data = fs::read(&path).unwrap();
+        println!("replaying {}", path.display());
+        harness(&data);
+    }
+}
+
+#[test]
+fn store_ops() {
+    replay("store_ops", stern_fuzz::store_ops);
+}
+
+#[test]
+fn heap_layout() {
+    replay("heap_layout", stern_fuzz::heap_layout);
+}
@@ -0,0 +1,329 @@
+//! Tables of heap atoms, used by [`Bits32`](crate::Bits32) atoms.
+//!
+//! 32-bit atoms are too small to hold a pointer. Instead, a [`HeapAtom`] gets
+//! a slot in an arena the first time it is used as a 32-bit atom, and those
+//! atoms store the arena's number and the slot's index. Slots are recycled
+//! once their atom is freed.
+//!
+//! Every [`AtomStore`] claims an arena of its own while it is alive, and its
+//! atoms get their slots there, so stores don't wait on each other's locks.
+//! The shards of a [`SharedAtomStore`] share a single arena.
+//! Arena 0 is shared, by atoms whose store found every other arena claimed
+//! and by atoms whose arena is full. Once a store is gone and its arena has
+//! no slots in use, the arena's memory is freed and another store can claim
+//! it.
+//!
+//! Each arena is split into buckets that double in size, so it can grow
+//! without moving existing slots, and lookups never take a lock. Bucket `b`
+//! holds slots `2^b..2^(b+1)`.
+//!
+//! [`AtomStore`]: crate::AtomStore
+//! [`SharedAtomStore`]: crate::SharedAtomStore
+//! [`HeapAtom`]: crate::heap::HeapAtom
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::num::NonZeroU32;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+/// Indexes must fit in the 30 bits above an atom's tag.
+pub(crate) const INDEX_BITS: u32 = u32::BITS - 2;
+/// The high bits of an index pick the arena, and the rest the slot in it.
+const ARENA_BITS: u32 = 6;
+const SLOT_BITS: u32 = INDEX_BITS - ARENA_BITS;
+const SLOT_MASK: u32 = (1 << SLOT_BITS) - 1;
+const ARENAS: usize = 1 << ARENA_BITS;
+const BUCKETS: usize = SLOT_BITS as usize;
+/// The arena shared by atoms that can't get a slot in their own.
+const SHARED: u32 = 0;
+
+type Slot = AtomicPtr<u8>;
+
+struct Arena {
+    buckets: [AtomicPtr<Slot>; BUCKETS],
+    state: Mutex<ArenaState>,
+}
+
+struct ArenaState {
+    /// Smallest slot that has never been handed out.
+    next: u32,
+    /// Slots of freed atoms, ready to be reused.
+    free: Vec<NonZeroU32>,
+    /// Slots in use.
+    used: u32,
+    /// Whether a store owns this arena.
+    claimed: bool,
+}
+
+impl Arena {
+    const fn new() -> Self {
+        Self {
+            buckets: [const { AtomicPtr::new(ptr::null_mut()) }; BUCKETS],
+            state: Mutex::new(ArenaState {
+                next: 1,
+                free: Vec::new(),
+                used: 0,
+                claimed: false,
+            }),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ArenaState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Store `atom` in a free slot and return the slot, or `None` if the
+    /// arena is full.
+    fn insert(&self, atom: *mut u8) -> Option<NonZeroU32> {
+        let mut state = self.lock();
+        let slot = if let Some(slot) = state.free.pop() {
+            slot
+        } else {
+            let slot = state.next;
+            if slot > SLOT_MASK {
+                return None;
+            }
+            state.next += 1;
+            // SAFETY: `next` starts at 1 and only grows
+            unsafe { NonZeroU32::new_unchecked(slot) }
+        };
+        state.used += 1;
+
+        let (bucket, offset) = locate(slot);
+        let mut slots = self.buckets[bucket].load(Ordering::Acquire);
+        if slots.is_null() {
+            // Buckets are only created and freed while holding the lock, so
+            // nobody can race us here.
+            let new: Box<[Slot]> = (0..1_usize << bucket)
+                .map(|_| AtomicPtr::new(ptr::null_mut()))
+                .collect();
+            slots = Box::into_raw(new).cast::<Slot>();
+            self.buckets[bucket].store(slots, Ordering::Release);
+        }
+        // SAFETY: bucket `b` has 2^b slots, and `offset` is less than that.
+        unsafe { (*slots.add(offset)).store(atom, Ordering::Release) };
+
+        Some(slot)
+    }
+
+    /// Free `slot` so it can be reused.
+    ///
+    /// # Safety
+    /// `slot` must have come from [`Arena::insert`], and no atom may use it
+    /// anymore.
+    unsafe fn remove(&self, slot: NonZeroU32) {
+        let mut state = self.lock();
+        let (bucket, offset) = locate(slot);
+        let slots = self.buckets[bucket].load(Ordering::Acquire);
+        (*slots.add(offset)).store(ptr::null_mut(), Ordering::Release);
+        state.free.push(slot);
+        state.used -= 1;
+        if state.used == 0 && !state.claimed {
+            self.reset(&mut state);
+        }
+    }
+
+    /// Free every bucket. No slot may be in use.
+    fn reset(&self, state: &mut ArenaState) {
+        debug_assert_eq!(state.used, 0);
+        for (bucket, slots) in self.buckets.iter().enumerate() {
+            let slots = slots.swap(ptr::null_mut(), Ordering::AcqRel);
+            if !slots.is_null() {
+                // SAFETY: made by `Arena::insert` from a boxed slice of this
+                // length, and no atom can read it anymore.
+                drop(unsafe { Box::from_raw(ptr::slice_from_raw_parts_mut(slots, 1 << bucket)) });
+            }
+        }
+        state.next = 1;
+        state.free = Vec::new();
+    }
+}
+
+static ARENA_LIST: [Arena; ARENAS] = [const { Arena::new() }; ARENAS];
+
+/// Arenas no store has claimed. Arena 0 is never claimed.
+static UNCLAIMED: Mutex<Unclaimed> = Mutex::new(Unclaimed {
+    next: SHARED + 1,
+    free: Vec::new(),
+});
+
+struct Unclaimed {
+    /// Smallest arena that has never been claimed.
+    next: u32,
+    /// Arenas given back by dropped stores.
+    free: Vec<u32>,
+}
+
+/// An arena a store owns. Gives it back when dropped.
+#[derive(Debug)]
+pub(crate) struct Claim {
+    arena: u32,
+}
+
+impl Claim {
+    /// Claim an unused arena, or use the shared one if there is none.
+    pub fn new() -> Self {
+        let mut unclaimed = UNCLAIMED.lock().unwrap_or_else(PoisonError::into_inner);
+        let arena = unclaimed.free.pop().unwrap_or_else(|| {
+            if unclaimed.next as usize == ARENAS {
+                return SHARED;
+            }
+            unclaimed.next += 1;
+            unclaimed.next - 1
+        });
+        drop(unclaimed);
+        if arena != SHARED {
+            ARENA_LIST[arena as usize].lock().claimed = true;
+        }
+        Self { arena }
+    }
+
+    /// What a heap atom made by the store starts with in its `slot`, so that
+    /// it gets a slot in this arena once it needs one.
+    #[inline]
+    pub fn tag(&self) -> u32 {
+        self.arena << SLOT_BITS
+    }
+}
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        if self.arena == SHARED {
+            return;
+        }
+        let arena = &ARENA_LIST[self.arena as usize];
+        let mut state = arena.lock();
+        state.claimed = false;
+        if state.used == 0 {
+            arena.reset(&mut state);
+        }
+        drop(state);
+        UNCLAIMED
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .free
+            .push(self.arena);
+    }
+}
+
+/// Whether `index`, read from a heap atom's `slot`, is a slot it was given,
+/// rather than the [tag](Claim::tag) of the arena it should get one in.
+#[inline]
+pub(crate) const fn has_slot(index: u32) -> bool {
+    index & SLOT_MASK != 0
+}
+
+/// Arena, bucket and offset within the bucket of the slot at `index`, which
+/// must [have a slot](has_slot).
+#[inline(always)]
+const fn locate_index(index: NonZeroU32) -> (usize, usize, usize) {
+    debug_assert!(has_slot(index.get()));
+    // SAFETY: the slot bits aren't all 0
+    let slot = unsafe { NonZeroU32::new_unchecked(index.get() & SLOT_MASK) };
+    let (bucket, offset) = locate(slot);
+    ((index.get() >> SLOT_BITS) as usize, bucket, offset)
+}
+
+/// Bucket and offset within it of a slot.
+#[inline(always)]
+const fn locate(slot: NonZeroU32) -> (usize, usize) {
+    let bucket = slot.ilog2();
+    (bucket as usize, (slot.get() - (1 << bucket)) as usize)
+}
+
+/// Store `atom` in a free slot and return the slot's index. `tag` picks the
+/// arena, and is what the atom's `slot` held before.
+///
+/// # Panics
+/// If both the arena and the shared arena are full.
+pub(crate) fn insert(tag: u32, atom: *mut u8) -> NonZeroU32 {
+    debug_assert!(!has_slot(tag));
+    let arena = tag >> SLOT_BITS;
+    let (arena, slot) = ARENA_LIST[arena as usize]
+        .insert(atom)
+        .map(|slot| (arena, slot))
+        .or_else(|| Some((SHARED, ARENA_LIST[SHARED as usize].insert(atom)?)))
+        .expect("too many heap atoms");
+    // SAFETY: `slot` is non-zero
+    unsafe { NonZeroU32::new_unchecked(arena << SLOT_BITS | slot.get()) }
+}
+
+/// Get the atom at `index`.
+///
+/// # Safety
+/// `index` must have come from [`insert`], and not been [`remove`]d since.
+#[inline]
+pub(crate) unsafe fn get(index: NonZeroU32) -> *mut u8 {
+    let (arena, bucket, offset) = locate_index(index);
+    let slots = ARENA_LIST[arena].buckets[bucket].load(Ordering::Acquire);
+    (*slots.add(offset)).load(Ordering::Acquire)
+}
+
+/// Free `index` so it can be reused.
+///
+/// # Safety
+/// `index` must have come from [`insert`], and no atom may use it anymore.
+pub(crate) unsafe fn remove(index: NonZeroU32) {
+    let arena = (index.get() >> SLOT_BITS) as usize;
+    ARENA_LIST[arena].remove(NonZeroU32::new_unchecked(index.get() & SLOT_MASK));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_locate() {
+        let locate_index = |i| locate_index(NonZeroU32::new(i).unwrap());
+        assert_eq!(locate_index(1), (0, 0, 0));
+        assert_eq!(locate_index(2), (0, 1, 0));
+        assert_eq!(locate_index(3), (0, 1, 1));
+        assert_eq!(locate_index(4), (0, 2, 0));
+        assert_eq!(locate_index(7), (0, 2, 3));
+        assert_eq!(locate_index(3 << SLOT_BITS | 5), (3, 2, 1));
+        assert_eq!(
+            locate_index((1 << INDEX_BITS) - 1),
+            (ARENAS - 1, BUCKETS - 1, (1 << (BUCKETS - 1)) - 1)
+        );
+    }
+
+    #[test]
+    fn test_reuse() {
+        let mut value = 0_u8;
+        let a = insert(SHARED, &mut value);
+        assert_eq!(unsafe { get(a) }, &mut value as *mut u8);
+        unsafe { remove(a) };
+        // other tests may be inserting at the same time, so we can't rely on
+        // getting `a` back.
+        let b = insert(SHARED, &mut value);
+        assert_eq!(unsafe { get(b) }, &mut value as *mut u8);
+        unsafe { remove(b) };
+    }
+
+    #[test]
+    fn test_claims() {
+        let mut value = 0_u8;
+        let (a, b) = (Claim::new(), Claim::new());
+        assert_ne!(a.arena, SHARED);
+        assert_ne!(a.arena, b.arena);
+
+        let index = insert(a.tag(), &mut value);
+        assert_eq!(index.get() >> SLOT_BITS, a.arena);
+        assert!(has_slot(index.get()));
+        assert!(!has_slot(a.tag()));
+
+        // the arena outlives its store while a slot is in use
+        let arena = &ARENA_LIST[a.arena as usize];
+        drop(a);
+        assert!(!arena.buckets[0].load(Ordering::Acquire).is_null());
+        assert_eq!(unsafe { get(index) }, &mut value as *mut u8);
+        unsafe { remove(index) };
+        let state = arena.lock();
+        // another test may have claimed the arena since
+        if !state.claimed && state.used == 0 {
+            assert!(arena.buckets[0].load(Ordering::Acquire).is_null());
+        }
+    }
+}
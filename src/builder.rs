@@ -0,0 +1,141 @@
+use alloc::string::String;
+use core::fmt;
+
+use crate::hash::AtomHasher;
+use crate::store::{atom_from_parts, limit_exceeded, AtomStore};
+use crate::tags::MAX_INLINE_LEN;
+use crate::Atom;
+
+/// Builds an [`Atom`] piece by piece.
+///
+/// Text is kept on the stack while it still fits in an inline atom, and only
+/// spills to the heap once it grows past that. It is hashed as it is written,
+/// so finishing a long atom does not need to hash it again.
+///
+/// ```
+/// use core::fmt::Write;
+/// use stern::AtomBuilder;
+///
+/// let mut builder = AtomBuilder::new();
+/// builder.push_str("_ZN");
+/// write!(builder, "{}{}", 3, "foo").unwrap();
+/// assert_eq!(builder.finish(), "_ZN3foo");
+/// ```
+#[derive(Default, Clone)]
+pub struct AtomBuilder {
+    inline: [u8; MAX_INLINE_LEN],
+    len: usize,
+    /// Holds all text written so far once it no longer fits in `inline`.
+    spilled: String,
+    hasher: AtomHasher,
+}
+
+impl AtomBuilder {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a builder that will not need to reallocate until it holds
+    /// `capacity` bytes.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let mut builder = Self::new();
+        if capacity > MAX_INLINE_LEN {
+            builder.spilled.reserve(capacity);
+        }
+        builder
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    #[inline]
+    fn is_spilled(&self) -> bool {
+        self.len > MAX_INLINE_LEN
+    }
+
+    /// The text written so far.
+    pub fn as_str(&self) -> &str {
+        if self.is_spilled() {
+            &self.spilled
+        } else {
+            // SAFETY: only whole `str`s get written to `inline`
+            unsafe { core::str::from_utf8_unchecked(&self.inline[..self.len]) }
+        }
+    }
+
+    pub fn push_str(&mut self, s: &str) {
+        self.hasher.write_str(s);
+        let len = self.len + s.len();
+        if self.is_spilled() {
+            self.spilled.push_str(s);
+        } else if len <= MAX_INLINE_LEN {
+            self.inline[self.len..len].copy_from_slice(s.as_bytes());
+        } else {
+            // SAFETY: only whole `str`s get written to `inline`
+            let inline = unsafe { core::str::from_utf8_unchecked(&self.inline[..self.len]) };
+            self.spilled.reserve(len);
+            self.spilled.push_str(inline);
+            self.spilled.push_str(s);
+        }
+        self.len = len;
+    }
+
+    #[inline]
+    pub fn push(&mut self, c: char) {
+        self.push_str(c.encode_utf8(&mut [0; 4]));
+    }
+
+    /// Finish building, interning the result in this thread's global store.
+    pub fn finish(self) -> Atom<'static> {
+        if self.is_spilled() {
+            atom_from_parts(&[&self.spilled], self.len, self.hasher.finish())
+        } else {
+            Atom::new_inline_impl(self.as_str())
+        }
+    }
+
+    /// Finish building, interning the result in `store`.
+    ///
+    /// # Panics
+    /// If the result would take `store` over one of its limits, like
+    /// [`AtomStore::atom`].
+    pub fn finish_in<V: Default>(self, store: &mut AtomStore<'_, V>) -> Atom<'static> {
+        if self.is_spilled() {
+            store.add_atom_parts(&[&self.spilled], self.len, self.hasher.finish())
+        } else {
+            store
+                .limits
+                .check_len(self.len)
+                .unwrap_or_else(limit_exceeded);
+            Atom::new_inline_impl(self.as_str())
+        }
+    }
+}
+
+impl fmt::Write for AtomBuilder {
+    #[inline]
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.push_str(s);
+        Ok(())
+    }
+
+    #[inline]
+    fn write_char(&mut self, c: char) -> fmt::Result {
+        self.push(c);
+        Ok(())
+    }
+}
+
+impl fmt::Debug for AtomBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("AtomBuilder").field(&self.as_str()).finish()
+    }
+}
@@ -0,0 +1,233 @@
+//! Options for making an [`AtomStore`].
+
+use core::fmt;
+
+use crate::id::{StoreIds, STORE_IDS};
+use crate::store::AtomStore;
+
+/// Makes [`AtomStore`]s with a chosen capacity, limits and [`IdPolicy`].
+///
+/// Limits protect a store from untrusted input. Once adding a string would
+/// go over one, [`AtomStore::try_atom`] and the other `try_` methods return a
+/// [`StoreError`], and the methods without `try_` panic.
+///
+/// The hasher can't be picked. Every store hashes strings with
+/// [`AtomHasher`](crate::AtomHasher): the hash is kept in each heap atom,
+/// atoms from different stores compare their hashes to tell they differ, and
+/// [`AtomStore::atom_prehashed`] callers compute it themselves.
+///
+/// ```
+/// use stern::{AtomStore, StoreError};
+///
+/// let mut store: AtomStore = AtomStore::builder()
+///     .max_entries(1)
+///     .max_len(64)
+///     .build();
+///
+/// assert!(store.try_atom("the_first_long_identifier").is_ok());
+/// assert_eq!(
+///     store.try_atom("the_second_long_identifier"),
+///     Err(StoreError::TooManyEntries { limit: 1 }),
+/// );
+/// assert_eq!(
+///     store.try_atom("x".repeat(100)),
+///     Err(StoreError::TooLong { len: 100, limit: 64 }),
+/// );
+/// ```
+#[derive(Debug, Clone, Copy)]
+#[must_use]
+pub struct AtomStoreBuilder {
+    pub(crate) capacity: usize,
+    pub(crate) limits: Limits,
+    pub(crate) id_policy: IdPolicy,
+    pub(crate) ids: &'static StoreIds,
+}
+
+impl Default for AtomStoreBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AtomStoreBuilder {
+    /// Entries a store has room for before it first grows.
+    pub const DEFAULT_CAPACITY: usize = 256;
+
+    /// Settings for a store like [`AtomStore::new`] makes: no limits, and a
+    /// [`unique ID`](IdPolicy::Unique).
+    pub fn new() -> Self {
+        Self {
+            capacity: Self::DEFAULT_CAPACITY,
+            limits: Limits::NONE,
+            id_policy: IdPolicy::Unique,
+            ids: &STORE_IDS,
+        }
+    }
+
+    /// Make room for `capacity` entries up front.
+    pub const fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// The most entries the store can have, not counting its parents.
+    pub const fn max_entries(mut self, max_entries: usize) -> Self {
+        self.limits.entries = max_entries;
+        self
+    }
+
+    /// The most bytes of string the store's entries can hold in total.
+    pub const fn max_bytes(mut self, max_bytes: usize) -> Self {
+        self.limits.bytes = max_bytes;
+        self
+    }
+
+    /// The longest string the store accepts, in bytes. Unlike the other
+    /// limits, this also applies to short strings that don't need an entry.
+    pub const fn max_len(mut self, max_len: usize) -> Self {
+        self.limits.len = max_len;
+        self
+    }
+
+    /// How the store is told apart from other stores. See [`IdPolicy`].
+    pub const fn id_policy(mut self, id_policy: IdPolicy) -> Self {
+        self.id_policy = id_policy;
+        self
+    }
+
+    /// Take IDs from `ids` instead of the global ones, so that tests can run
+    /// out of them.
+    #[cfg(test)]
+    pub(crate) const fn ids(mut self, ids: &'static StoreIds) -> Self {
+        self.ids = ids;
+        self
+    }
+
+    /// Make the store.
+    ///
+    /// # Panics
+    /// If the store needs a [unique ID](IdPolicy::Unique), and every ID is
+    /// taken. See [`AtomStoreBuilder::try_build`].
+    pub fn build<'p, V>(self) -> AtomStore<'p, V> {
+        self.try_build().unwrap_or_else(|err| panic!("{err}"))
+    }
+
+    /// Like [`AtomStoreBuilder::build`], but returns an error instead of
+    /// panicking.
+    ///
+    /// # Errors
+    /// [`StoreError::OutOfIds`] if the store needs a
+    /// [unique ID](IdPolicy::Unique), and every ID is taken.
+    pub fn try_build<'p, V>(self) -> Result<AtomStore<'p, V>, StoreError> {
+        let id = match self.id_policy {
+            IdPolicy::Unique => Some(self.ids.acquire().ok_or(StoreError::OutOfIds)?),
+            IdPolicy::Anonymous => None,
+        };
+        Ok(AtomStore::from_builder(self, id))
+    }
+}
+
+/// How an [`AtomStore`] is told apart from other stores.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IdPolicy {
+    /// Give the store an ID of its own, and write it into every heap atom it
+    /// makes. Two atoms with the same ID are only equal if they are the same
+    /// pointer, so comparing them never looks at their strings.
+    ///
+    /// There are about 4 billion IDs. A store gives its ID back when it is
+    /// dropped, unless some of its atoms are still alive; those IDs can't be
+    /// used again.
+    #[default]
+    Unique,
+    /// Don't give the store an ID. Its heap atoms compare their strings
+    /// whenever they aren't the same pointer, which makes comparisons slower
+    /// but doesn't use up an ID.
+    Anonymous,
+}
+
+/// How big a store can get. See [`AtomStoreBuilder`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Limits {
+    pub entries: usize,
+    pub bytes: usize,
+    pub len: usize,
+}
+
+impl Limits {
+    pub const NONE: Self = Self {
+        entries: usize::MAX,
+        bytes: usize::MAX,
+        len: usize::MAX,
+    };
+
+    /// Check that a string of `len` bytes is allowed.
+    #[inline]
+    pub fn check_len(&self, len: usize) -> Result<(), StoreError> {
+        if len > self.len {
+            return Err(StoreError::TooLong {
+                len,
+                limit: self.len,
+            });
+        }
+        Ok(())
+    }
+
+    /// Check that an entry of `len` bytes can be added to a store that has
+    /// `entries` entries holding `bytes` bytes.
+    #[inline]
+    pub fn check_entry(&self, entries: usize, bytes: usize, len: usize) -> Result<(), StoreError> {
+        self.check_len(len)?;
+        if entries >= self.entries {
+            return Err(StoreError::TooManyEntries {
+                limit: self.entries,
+            });
+        }
+        if bytes.saturating_add(len) > self.bytes {
+            return Err(StoreError::TooManyBytes { limit: self.bytes });
+        }
+        Ok(())
+    }
+}
+
+/// An [`AtomStore`] couldn't be made, or adding a string would take it over
+/// one of its limits. See [`AtomStoreBuilder`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum StoreError {
+    /// The store already has as many entries as it can.
+    TooManyEntries { limit: usize },
+    /// The string would take the store's entries over this many bytes.
+    TooManyBytes { limit: usize },
+    /// The string is `len` bytes long, more than the store accepts.
+    TooLong { len: usize, limit: usize },
+    /// Every store ID is taken.
+    OutOfIds,
+    /// The hash passed with a string is not its hash. See
+    /// [`AtomStore::try_atom_prehashed`].
+    WrongHash,
+    /// The string's entry belongs to a parent store, whose payloads can't be
+    /// changed. See [`AtomStore::try_set_payload`].
+    InParent,
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooManyEntries { limit } => {
+                write!(f, "atom store is full: it can hold at most {limit} entries")
+            }
+            Self::TooManyBytes { limit } => {
+                write!(f, "atom store is full: it can hold at most {limit} bytes")
+            }
+            Self::TooLong { len, limit } => write!(
+                f,
+                "string of {len} bytes is longer than the atom store accepts ({limit} bytes)"
+            ),
+            Self::OutOfIds => f.write_str("every atom store ID is taken"),
+            Self::WrongHash => f.write_str("hash does not match its string"),
+            Self::InParent => f.write_str("entry belongs to a parent atom store"),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
@@ -0,0 +1,279 @@
+//! Read-only stores, made by [`AtomStore::freeze`](crate::AtomStore::freeze).
+
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+use core::marker::PhantomData;
+use core::mem;
+use core::num::NonZeroU32;
+
+use crate::hash::str_hash;
+use crate::heap::{HeapAtom, HeapRc};
+use crate::id::StoreIds;
+use crate::size::AtomSize;
+use crate::store::{entry_matches, parts_eq, BuildAtomHasher};
+use crate::tags::MAX_INLINE_LEN;
+use crate::Atom;
+
+/// An [`AtomStore`](crate::AtomStore) that can no longer change.
+///
+/// Frozen stores are `Send` and `Sync`, and looking strings up in them never
+/// takes a lock, so one store of builtin names can be shared by every thread.
+/// They can also be the parent of other stores. See
+/// [`AtomStore::with_frozen_parent`](crate::AtomStore::with_frozen_parent).
+///
+/// ```
+/// use stern::AtomStore;
+///
+/// let mut builtins = AtomStore::default();
+/// builtins.atom("addEventListener");
+/// let builtins = builtins.freeze().with_perfect_hash();
+///
+/// std::thread::scope(|scope| {
+///     scope.spawn(|| assert!(builtins.get("addEventListener").is_some()));
+///     scope.spawn(|| assert!(builtins.get("removeEventListener").is_none()));
+/// });
+/// ```
+#[derive(Debug)]
+pub struct FrozenAtomStore<V = ()> {
+    table: Table<V>,
+    id: Option<NonZeroU32>,
+    ids: &'static StoreIds,
+}
+
+#[derive(Debug)]
+enum Table<V> {
+    Map(hashbrown::HashMap<HeapRc, V, BuildAtomHasher>),
+    Perfect(PerfectTable<V>),
+}
+
+impl<V> FrozenAtomStore<V> {
+    pub(crate) fn new(
+        data: hashbrown::HashMap<HeapRc, V, BuildAtomHasher>,
+        id: Option<NonZeroU32>,
+        ids: &'static StoreIds,
+    ) -> Self {
+        Self {
+            table: Table::Map(data),
+            id,
+            ids,
+        }
+    }
+
+    /// Rebuild the table as a minimal perfect hash table. Every lookup then
+    /// checks exactly one entry, which makes misses cheaper.
+    ///
+    /// Building the table takes longer than freezing the store. If it can't
+    /// be built, because two strings have the same hash, the store is left
+    /// as it is.
+    #[must_use]
+    pub fn with_perfect_hash(mut self) -> Self {
+        let Table::Map(data) = &mut self.table else {
+            return self;
+        };
+        let entries: Vec<(HeapRc, V)> = mem::take(data).into_iter().collect();
+        self.table = match PerfectTable::new(entries) {
+            Ok(table) => Table::Perfect(table),
+            Err(entries) => Table::Map(entries.into_iter().collect()),
+        };
+        self
+    }
+
+    /// The atom for `s`, if it is in this store. Strings short enough to be
+    /// inlined don't need an entry, and are always found.
+    pub fn get<S: AsRef<str>>(&self, s: S) -> Option<Atom<'static>> {
+        let s = s.as_ref();
+        if s.len() <= MAX_INLINE_LEN {
+            return Some(Atom::new_inline_impl(s));
+        }
+        let (entry, _) = self.find(&[s], s.len(), str_hash(s))?;
+        Some(Atom {
+            inner: entry.clone().into_tagged(),
+            marker: PhantomData,
+        })
+    }
+
+    /// The payload of `atom`'s entry in this store, if it has one.
+    pub fn payload<S: AtomSize>(&self, atom: &Atom<'_, S>) -> Option<&V> {
+        let text = atom.to_str();
+        let (hash, heap) = if atom.is_heap() {
+            let heap = unsafe { HeapAtom::deref_from(atom.inner) };
+            (heap.hash(), Some(heap))
+        } else {
+            (str_hash(&text), None)
+        };
+        self.find_with(hash, |key| entry_matches(key, hash, &text, heap))
+            .map(|(_, value)| value)
+    }
+
+    pub(crate) fn find(&self, parts: &[&str], len: usize, hash: u64) -> Option<(&HeapRc, &V)> {
+        self.find_with(hash, |key| {
+            key.hash() == hash && key.len() == len && parts_eq(key.as_str(), parts)
+        })
+    }
+
+    pub(crate) fn find_with(
+        &self,
+        hash: u64,
+        eq: impl Fn(&HeapRc) -> bool,
+    ) -> Option<(&HeapRc, &V)> {
+        match &self.table {
+            Table::Map(data) => data.raw_entry().from_hash(hash, eq),
+            Table::Perfect(table) => table
+                .get(hash)
+                .filter(|(key, _)| eq(key))
+                .map(|(key, value)| (key, value)),
+        }
+    }
+}
+
+impl<V> Drop for FrozenAtomStore<V> {
+    fn drop(&mut self) {
+        let Some(id) = self.id else {
+            return;
+        };
+        match &self.table {
+            Table::Map(data) => self.ids.release_unused(id, data.keys()),
+            Table::Perfect(table) => self
+                .ids
+                .release_unused(id, table.entries.iter().map(|(key, _)| key)),
+        }
+    }
+}
+
+/// Average number of entries per bucket in a [`PerfectTable`]. Bigger
+/// buckets make the table smaller, but slower to build.
+const BUCKET_SIZE: usize = 4;
+
+/// Displacements to try per bucket before giving up on building a
+/// [`PerfectTable`].
+const MAX_DISPLACEMENT: u32 = 1 << 20;
+
+/// A minimal perfect hash table, built with hash-and-displace.
+///
+/// Entries are split into buckets by their hash. Each bucket has a
+/// displacement, picked so that mixing it into the hashes of the bucket's
+/// entries sends them all to different slots. There are exactly as many slots
+/// as entries.
+#[derive(Debug)]
+struct PerfectTable<V> {
+    displacements: Box<[u32]>,
+    entries: Box<[(HeapRc, V)]>,
+}
+
+impl<V> PerfectTable<V> {
+    /// Build a table for `entries`, or give them back if some of their hashes
+    /// collide.
+    fn new(entries: Vec<(HeapRc, V)>) -> Result<Self, Vec<(HeapRc, V)>> {
+        let len = entries.len();
+        let bucket_count = len.div_ceil(BUCKET_SIZE).max(1);
+        let mut buckets: Vec<Vec<usize>> = vec![Vec::new(); bucket_count];
+        for (index, (key, _)) in entries.iter().enumerate() {
+            buckets[bucket(key.hash(), bucket_count)].push(index);
+        }
+
+        // Place big buckets first, while most slots are still free.
+        let mut order: Vec<usize> = (0..bucket_count).collect();
+        order.sort_unstable_by_key(|&bucket| Reverse(buckets[bucket].len()));
+
+        let mut displacements = vec![0; bucket_count];
+        let mut slot_of = vec![0; len];
+        let mut taken = vec![false; len];
+        let mut slots = Vec::with_capacity(BUCKET_SIZE);
+        for bucket in order {
+            let members = &buckets[bucket];
+            if members.is_empty() {
+                break;
+            }
+            let found = (0..MAX_DISPLACEMENT).find(|&displacement| {
+                slots.clear();
+                for &index in members {
+                    let slot = slot(entries[index].0.hash(), displacement, len);
+                    if taken[slot] || slots.contains(&slot) {
+                        return false;
+                    }
+                    slots.push(slot);
+                }
+                true
+            });
+            let Some(displacement) = found else {
+                return Err(entries);
+            };
+
+            displacements[bucket] = displacement;
+            for (&index, &slot) in members.iter().zip(&slots) {
+                taken[slot] = true;
+                slot_of[index] = slot;
+            }
+        }
+
+        let mut placed: Vec<Option<(HeapRc, V)>> = (0..len).map(|_| None).collect();
+        for (entry, slot) in entries.into_iter().zip(slot_of) {
+            placed[slot] = Some(entry);
+        }
+        Ok(Self {
+            displacements: displacements.into_boxed_slice(),
+            entries: placed.into_iter().map(Option::unwrap).collect(),
+        })
+    }
+
+    /// The only entry that could have this hash.
+    #[inline]
+    fn get(&self, hash: u64) -> Option<&(HeapRc, V)> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        let displacement = self.displacements[bucket(hash, self.displacements.len())];
+        let entry = &self.entries[slot(hash, displacement, self.entries.len())];
+        (entry.0.hash() == hash).then_some(entry)
+    }
+}
+
+/// Map `hash` to `0..len` without a division.
+#[inline(always)]
+#[allow(clippy::cast_possible_truncation)]
+fn reduce(hash: u64, len: usize) -> usize {
+    ((u128::from(hash) * len as u128) >> 64) as usize
+}
+
+#[inline(always)]
+fn bucket(hash: u64, bucket_count: usize) -> usize {
+    reduce(hash.rotate_left(32), bucket_count)
+}
+
+#[inline(always)]
+fn slot(hash: u64, displacement: u32, len: usize) -> usize {
+    // murmur3's finalizer
+    let mut x = hash ^ u64::from(displacement).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    x ^= x >> 33;
+    x = x.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    x ^= x >> 33;
+    reduce(x, len)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::AtomStore;
+
+    #[test]
+    fn test_perfect_hash() {
+        for count in [0, 1, 2, 5, 100, 1000] {
+            let mut store: AtomStore<usize> = AtomStore::new();
+            let names: Vec<_> = (0..count).map(|i| alloc::format!("builtin_{i}")).collect();
+            for (i, name) in names.iter().enumerate() {
+                store.intern_with(name, || i);
+            }
+            let atoms: Vec<_> = names.iter().map(|name| store.atom(name)).collect();
+            let frozen = store.freeze().with_perfect_hash();
+            assert!(matches!(frozen.table, Table::Perfect(_)));
+
+            for (i, (name, atom)) in names.iter().zip(&atoms).enumerate() {
+                assert_eq!(frozen.get(name).unwrap().inner, atom.inner);
+                assert_eq!(frozen.payload(atom), Some(&i));
+            }
+            assert!(frozen.get("not_a_builtin_name").is_none());
+        }
+    }
+}
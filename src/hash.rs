@@ -0,0 +1,161 @@
+use core::hash::Hasher;
+
+/// Multiplier borrowed from `rustc-hash`'s `FxHasher`.
+const K: u64 = 0xf135_7aea_2e62_a9c5;
+
+/// An incremental string hasher.
+///
+/// Unlike `FxHasher`, the result does not depend on how the input is split up:
+/// writing `"foo"` and then `"bar"` gives the same hash as writing `"foobar"`.
+/// This lets us hash strings piece by piece while building them, without
+/// first copying them into a contiguous buffer.
+///
+/// This is the hash [`AtomStore`](crate::AtomStore) uses for heap atoms. A
+/// lexer that hashes identifiers as it scans them can pass the result to
+/// [`AtomStore::atom_prehashed`](crate::AtomStore::atom_prehashed), which
+/// checks it.
+///
+/// ```
+/// use stern::AtomHasher;
+///
+/// let mut hasher = AtomHasher::new();
+/// for byte in b"some_identifier" {
+///     hasher.write_bytes(&[*byte]);
+/// }
+/// assert_eq!(hasher.finish(), AtomHasher::hash_str("some_identifier"));
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AtomHasher {
+    hash: u64,
+    /// Bytes that don't yet fill a whole word, packed little-endian.
+    tail: u64,
+    tail_len: u8,
+    len: u64,
+}
+
+impl AtomHasher {
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Hash a whole string at once.
+    #[inline]
+    pub fn hash_str(s: &str) -> u64 {
+        str_hash(s)
+    }
+
+    #[inline(always)]
+    fn add_word(&mut self, word: u64) {
+        self.hash = (self.hash.rotate_left(5) ^ word).wrapping_mul(K);
+    }
+
+    pub fn write_str(&mut self, s: &str) {
+        self.write_bytes(s.as_bytes());
+    }
+
+    #[allow(clippy::missing_panics_doc)]
+    pub fn write_bytes(&mut self, mut bytes: &[u8]) {
+        self.len += bytes.len() as u64;
+
+        // top up a partially filled word first
+        while self.tail_len != 0 {
+            let Some((&byte, rest)) = bytes.split_first() else {
+                return;
+            };
+            self.tail |= u64::from(byte) << (self.tail_len * 8);
+            self.tail_len += 1;
+            bytes = rest;
+            if self.tail_len == 8 {
+                self.add_word(self.tail);
+                self.tail = 0;
+                self.tail_len = 0;
+            }
+        }
+
+        let mut words = bytes.chunks_exact(8);
+        for word in &mut words {
+            self.add_word(u64::from_le_bytes(word.try_into().unwrap()));
+        }
+
+        for (i, &byte) in words.remainder().iter().enumerate() {
+            self.tail |= u64::from(byte) << (i * 8);
+        }
+        #[allow(clippy::cast_possible_truncation)]
+        {
+            self.tail_len = words.remainder().len() as u8;
+        }
+    }
+
+    pub fn finish(&self) -> u64 {
+        let mut this = *self;
+        if this.tail_len != 0 {
+            this.add_word(this.tail);
+        }
+        this.add_word(this.len);
+        // Fx leaves the low bits poorly mixed, but hashbrown picks buckets
+        // with them.
+        this.hash.rotate_left(26)
+    }
+}
+
+impl Hasher for AtomHasher {
+    #[inline]
+    fn finish(&self) -> u64 {
+        AtomHasher::finish(self)
+    }
+
+    #[inline]
+    fn write(&mut self, bytes: &[u8]) {
+        self.write_bytes(bytes);
+    }
+}
+
+pub(crate) fn str_hash(s: &str) -> u64 {
+    let mut hasher = AtomHasher::default();
+    hasher.write_str(s);
+    hasher.finish()
+}
+
+/// Hash the concatenation of `parts`. Same as calling [`str_hash`] on the
+/// concatenated string.
+pub(crate) fn parts_hash(parts: &[&str]) -> u64 {
+    let mut hasher = AtomHasher::default();
+    for part in parts {
+        hasher.write_str(part);
+    }
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_split_independent() {
+        let s = "the quick brown fox jumps over the lazy dog";
+        let expected = str_hash(s);
+        for i in 0..=s.len() {
+            for j in i..=s.len() {
+                assert_eq!(parts_hash(&[&s[..i], &s[i..j], &s[j..]]), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_byte_at_a_time() {
+        let s = "identifier_scanned_one_byte_at_a_time";
+        let mut hasher = AtomHasher::new();
+        for byte in s.bytes() {
+            hasher.write_bytes(&[byte]);
+            let _ = hasher.finish();
+        }
+        assert_eq!(hasher.finish(), AtomHasher::hash_str(s));
+    }
+
+    #[test]
+    fn test_length_matters() {
+        assert_ne!(str_hash(""), str_hash("\0"));
+        assert_ne!(str_hash("a"), str_hash("a\0"));
+    }
+}
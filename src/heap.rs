@@ -3,54 +3,70 @@ extern crate alloc;
 
 use core::alloc::Layout;
 use core::hash::{Hash, Hasher};
-use core::mem::{size_of, transmute};
+use core::mem::{size_of, ManuallyDrop};
 use core::num::NonZeroU32;
-use core::ptr::{self};
-use core::sync::atomic;
+use core::ops::Deref;
+use core::ptr::{self, NonNull};
+use core::sync::atomic::{self, AtomicU32, Ordering};
 use core::{fmt, slice};
-use rustc_hash::FxHasher;
-
-use alloc::sync::Arc;
-
-use alloc::boxed::Box;
 
+use crate::arena;
+use crate::hash::{parts_hash, str_hash};
+use crate::size::AtomSize;
 use crate::tags::{Tag, TaggedValue};
 use crate::ALIGNMENT;
 
+/// Refcounts at or above this mark an immortal atom, which is never freed.
+/// Cloning and dropping an immortal atom leaves its refcount alone.
+const IMMORTAL_THRESHOLD: u32 = 1 << (u32::BITS - 2);
+/// The refcount immortal atoms are pinned at. It sits in the middle of the
+/// immortal range, so racing increments and decrements can't move it out.
+const IMMORTAL_REFCOUNT: u32 = IMMORTAL_THRESHOLD + IMMORTAL_THRESHOLD / 2;
+
 #[derive(Debug)]
 #[repr(C)]
 pub struct Header {
+    /// Number of [`HeapRc`]s and heap [`Atom`](crate::Atom)s pointing to this
+    /// allocation.
+    pub(crate) refcount: AtomicU32,
+    /// Index of this atom in the `arena`. Atoms only get a slot the first
+    /// time they are used as an [`Atom32`](crate::Atom32); until then, this
+    /// is the [tag](crate::arena::Claim::tag) of the arena to get it in.
+    pub(crate) slot: AtomicU32,
     /// Length of the string
     pub(crate) len: u32,
     pub(crate) store_id: Option<NonZeroU32>,
     /// Pre-computed hash
     pub(crate) hash: u64,
 }
-static_assertions::const_assert!(size_of::<Header>() == 16);
+static_assertions::const_assert!(size_of::<Header>() == 24);
 static_assertions::assert_eq_align!(Header, u64);
 
 impl Header {
-    unsafe fn new_unchecked(s: &str, store_id: Option<NonZeroU32>) -> Self {
-        assert_unchecked!(s.len() < u32::MAX as usize, "string's length overflows u32");
+    unsafe fn new_unchecked(
+        len: usize,
+        hash: u64,
+        store_id: Option<NonZeroU32>,
+        arena: u32,
+    ) -> Self {
+        assert_unchecked!(len < u32::MAX as usize, "string's length overflows u32");
 
         #[allow(clippy::cast_possible_truncation)]
         Self {
-            len: s.len() as u32,
+            refcount: AtomicU32::new(1),
+            slot: AtomicU32::new(arena),
+            len: len as u32,
             store_id,
-            hash: str_hash(s),
-        }
-    }
-}
-impl Default for Header {
-    fn default() -> Self {
-        Self {
-            len: 0,
-            store_id: None,
-            hash: str_hash(""),
+            hash,
         }
     }
 }
 
+/// A string allocated on the heap, prefixed by a [`Header`].
+///
+/// Always lives behind a pointer to an allocation made by
+/// [`HeapAtom::try_new_unchecked`]. The pointer's metadata is the length of
+/// the string.
 #[repr(C)]
 #[derive(Debug)]
 pub(crate) struct HeapAtom {
@@ -58,72 +74,46 @@ pub(crate) struct HeapAtom {
     pub(crate) string: str,
 }
 
-/// This has the same layout and representation as [`HeapAtom`], but can be
-/// constructed directly. Especially useful for sized slices.
-///
-/// The only use for this is to construct a [`HeapAtom`]. It gets casted
-/// immediately after construction.
-#[repr(C)]
-#[derive(Debug)]
-struct Generic<T: ?Sized> {
-    header: Header,
-    string: T,
-}
-
-#[repr(C)]
-struct SneakyArcInner<T: ?Sized> {
-    strong: atomic::AtomicUsize,
-
-    // the value usize::MAX acts as a sentinel for temporarily "locking" the
-    // ability to upgrade weak pointers or downgrade strong ones; this is used
-    // to avoid races in `make_mut` and `get_mut`.
-    weak: atomic::AtomicUsize,
-
-    data: T,
-}
-
-impl<T: ?Sized> SneakyArcInner<T> {
-    #[inline(always)]
-    #[must_use]
-    const unsafe fn into_data_ptr(ptr: *const Self) -> *const T {
-        ptr.byte_add(size_of::<SneakyArcInner<()>>()) as *const _
-    }
-
-    #[inline(always)]
+impl HeapAtom {
+    #[cfg(test)]
     #[must_use]
-    const unsafe fn into_data_ptr_mut(ptr: *mut Self) -> *mut T {
-        ptr.byte_add(size_of::<SneakyArcInner<()>>()) as *mut _
-    }
-}
-
-impl<T: ?Sized + fmt::Debug> fmt::Debug for SneakyArcInner<T> {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("SneakyArcInner")
-            .field("strong", &self.strong)
-            .field("weak", &self.weak)
-            .field("data", &&self.data)
-            .finish()
+    #[allow(clippy::new_ret_no_self)]
+    pub fn new(s: &str, store_id: Option<NonZeroU32>) -> HeapRc {
+        Self::from_parts(&[s], s.len(), str_hash(s), store_id, 0)
     }
-}
 
-impl HeapAtom {
+    /// Create a new [`HeapAtom`] holding the concatenation of `parts`, without
+    /// first joining them into a temporary string.
+    ///
+    /// `len` must be the sum of the lengths of `parts`, and `hash` must be
+    /// their [`str_hash`]. `arena` is the [tag](crate::arena::Claim::tag) of
+    /// the arena the atom gets a slot in if it is used as an
+    /// [`Atom32`](crate::Atom32).
     #[must_use]
-    pub fn new(s: &str, store_id: Option<NonZeroU32>) -> Arc<HeapAtom> {
-        assert!(u32::try_from(s.len()).is_ok(), "string is too long");
-        if s.is_empty() {
-            return unsafe { Self::zero_sized() };
-        }
+    pub fn from_parts(
+        parts: &[&str],
+        len: usize,
+        hash: u64,
+        store_id: Option<NonZeroU32>,
+        arena: u32,
+    ) -> HeapRc {
+        assert!(len < u32::MAX as usize, "string is too long");
+        debug_assert_eq!(len, parts.iter().map(|part| part.len()).sum::<usize>());
+        debug_assert_eq!(hash, parts_hash(parts));
 
-        unsafe { Self::try_new_unchecked(s, store_id) }.unwrap()
+        unsafe { Self::try_new_unchecked(parts, len, hash, store_id, arena) }.unwrap()
     }
 
     #[inline(never)]
     pub unsafe fn try_new_unchecked(
-        s: &str,
+        parts: &[&str],
+        len: usize,
+        hash: u64,
         store_id: Option<NonZeroU32>,
-    ) -> Result<Arc<HeapAtom>, &'static str> {
-        assert_unchecked!(s.len() < u32::MAX as usize);
-        let header = Header::new_unchecked(s, store_id);
+        arena: u32,
+    ) -> Result<HeapRc, &'static str> {
+        assert_unchecked!(len < u32::MAX as usize);
+        let header = Header::new_unchecked(len, hash, store_id, arena);
 
         let layout = Self::get_layout(header.len);
         debug_assert_eq!(layout.align(), 8);
@@ -140,116 +130,174 @@ impl HeapAtom {
             "pointer for new HeapAtom is not 8-byte aligned"
         );
 
-        let arc_inner: SneakyArcInner<()> = SneakyArcInner {
-            strong: atomic::AtomicUsize::new(1),
-            weak: atomic::AtomicUsize::new(1),
-            data: (),
-        };
-
         // write the data to the heap
         unsafe {
-            // ArcInner
-            ptr::copy_nonoverlapping(&arc_inner, ptr as *mut SneakyArcInner<()>, 1);
             // Header
-            let header_ptr = ptr.byte_add(size_of::<SneakyArcInner<()>>()) as *mut Header;
-            ptr::copy_nonoverlapping(&header, header_ptr, 1);
-            // HeapAtom
-            let string_ptr = header_ptr.byte_add(size_of::<Header>()) as *mut u8;
-            ptr::copy_nonoverlapping(s.as_ptr(), string_ptr, s.len());
+            ptr::write(ptr.cast::<Header>(), header);
+            // String
+            let mut string_ptr = ptr.add(size_of::<Header>());
+            for part in parts {
+                ptr::copy_nonoverlapping(part.as_ptr(), string_ptr, part.len());
+                string_ptr = string_ptr.add(part.len());
+            }
+            // Zero out the padding after the string so that strings can be
+            // compared a word at a time. See `HeapAtom::str_eq`.
+            let padding = layout.size() - (string_ptr as usize - ptr as usize);
+            debug_assert!(padding < ALIGNMENT);
+            ptr::write_bytes(string_ptr, 0, padding);
         }
 
-        // TODO: should we use Box semantics or NonNull semantics?
-        // fat pointer to dynamically-sized type (DST)
-        let fat_ptr: Arc<HeapAtom> = unsafe {
-            // The cast from slice to fat_raw cast seems to be adding 32 bytes
-            // to the pointer's size when slice is &mut [u8]. Confirmable via
-            // Layout::from_value(&slice).size(). Making this a usize slice
-            // seems to help, but not fully fix it? needs investigation.
-            // NOTE: ^ this only gets detected by Miri. Functional tests work
-            // both before and after.
-            let slice: &mut [usize] =
-                slice::from_raw_parts_mut(ptr as *mut usize, layout.size() / size_of::<usize>());
-            let fat_raw = slice as *mut [usize] as *mut SneakyArcInner<HeapAtom>;
-            #[cfg(debug_assertions)]
-            {
-                let fat_layout = Layout::for_value(fat_raw.as_ref().unwrap());
-                assert_eq!(fat_layout.align(), ALIGNMENT);
-                // FIXME
-                // assert_eq!(fat_layout.size(), layout.size());
-            }
-            // let fat_atom: *mut HeapAtom =
-            // fat_raw.byte_add(Self::data_offset()) as *mut _;
-            let fat_atom = SneakyArcInner::into_data_ptr_mut(fat_raw);
-            Arc::from_raw(fat_atom)
-        };
+        // SAFETY: `ptr` is non-null, and the allocation holds an initialized
+        // header followed by `len` bytes of string data.
+        let rc = unsafe { HeapRc::from_raw(Self::fat_ptr(NonNull::new_unchecked(ptr), len)) };
 
         // ensure layout integrity
-        debug_assert_eq!(fat_ptr.len(), s.len());
-        debug_assert_eq!(fat_ptr.as_str(), s);
+        debug_assert_eq!(Layout::for_value::<HeapAtom>(&rc), layout);
+        debug_assert_eq!(rc.len(), len);
+        debug_assert_eq!(rc.header.hash, str_hash(rc.as_str()));
 
-        Ok(fat_ptr)
+        Ok(rc)
     }
 
-    // FIXME: I don't think we actually need this function b/c zero-sized
-    // strings get inlined
-    #[must_use]
-    unsafe fn zero_sized() -> Arc<HeapAtom> {
-        let empty: Generic<[u8; 0]> = Generic {
-            header: Header::default(),
-            string: [],
-        };
-
-        // must be put on the heap b/c Arc expects to own its own heap
-        // allocation and will free() it. If it's on the stack, we'll get a SIGSEGV
-        let raw_ptr: *mut SneakyArcInner<Generic<[u8; 0]>> = Box::leak(Box::new(SneakyArcInner {
-            strong: atomic::AtomicUsize::new(1),
-            weak: atomic::AtomicUsize::new(1),
-            data: empty,
-        })) as *mut _;
-        // get pointer to our string struct. Arc::from_raw will find strong/weak
-        // by subtracting from the pointer passed to it, so we need to
-        // compensate by adding the same offset. This only works if, among other
-        // things, the pointer offset is 8.
-        let atom_ptr = unsafe { SneakyArcInner::into_data_ptr(raw_ptr) };
-        debug_assert!(atom_ptr.is_aligned());
-
-        // ensure layout is correct
-        #[cfg(debug_assertions)]
-        unsafe {
-            let atom = atom_ptr.as_ref().unwrap();
-            assert_eq!(atom.header.len, 0);
-            assert_eq!(atom.header.store_id, None);
-            assert_eq!(atom.string.as_ref(), "".as_bytes());
-        }
-
-        let raw = unsafe { Arc::from_raw(atom_ptr) };
-        let fat = raw as Arc<Generic<[u8]>>;
-
-        // cast Generic into a HeapAtom and ensure layout is consistent after
-        // Arc::from_raw
-        let arc: Arc<HeapAtom> = unsafe { transmute(fat) };
-        debug_assert_eq!(arc.len(), 0);
-        debug_assert_eq!(arc.as_str(), "");
+    /// Build a pointer to the [`HeapAtom`] starting at `ptr`, whose string is
+    /// `len` bytes long.
+    #[inline(always)]
+    const fn fat_ptr(ptr: NonNull<u8>, len: usize) -> NonNull<HeapAtom> {
+        let fat = ptr::slice_from_raw_parts(ptr.as_ptr(), len) as *mut HeapAtom;
+        // SAFETY: `ptr` is non-null, and casting keeps the address.
+        unsafe { NonNull::new_unchecked(fat) }
+    }
 
-        arc
+    /// # Safety
+    /// `tagged_ptr` must be a heap-owned value pointing to a live [`HeapAtom`].
+    #[must_use]
+    pub unsafe fn deref_from<'a, S: AtomSize>(tagged_ptr: TaggedValue<S>) -> &'a HeapAtom {
+        Self::ptr_from(tagged_ptr).as_ref()
     }
 
+    /// Take back ownership of the reference held by a heap-owned
+    /// [`TaggedValue`].
+    ///
+    /// # Safety
+    /// `tagged_ptr` must have been created from [`HeapRc::into_tagged`], and
+    /// must not be used again afterwards.
     #[must_use]
-    pub const unsafe fn deref_from<'a>(tagged_ptr: TaggedValue) -> &'a HeapAtom {
+    pub unsafe fn restore_rc<S: AtomSize>(tagged_ptr: TaggedValue<S>) -> HeapRc {
+        HeapRc::from_raw(Self::ptr_from(tagged_ptr))
+    }
+
+    unsafe fn ptr_from<S: AtomSize>(tagged_ptr: TaggedValue<S>) -> NonNull<HeapAtom> {
         debug_assert!(
             matches!(tagged_ptr.tag(), Tag::HeapOwned),
             "cannot deref a non heap-owned tagged value"
         );
 
-        let len: u32 = ptr::read(tagged_ptr.get_ptr().cast());
-        let fat_ptr = slice::from_raw_parts(tagged_ptr.get_ptr(), Self::sizeof(len));
-        transmute::<_, &'a HeapAtom>(fat_ptr)
+        let ptr = tagged_ptr.get_ptr();
+        let len = (*ptr.cast::<Header>()).len;
+        Self::fat_ptr(NonNull::new_unchecked(ptr.cast_mut()), len as usize)
     }
 
-    #[must_use]
-    pub unsafe fn restore_arc(tagged_ptr: TaggedValue) -> Arc<HeapAtom> {
-        let raw_ref = Self::deref_from(tagged_ptr);
-        Arc::from_raw(raw_ref as *const HeapAtom)
+    /// Index of the atom at `ptr` in the `arena`, giving it a slot if it
+    /// doesn't have one yet.
+    ///
+    /// # Safety
+    /// `ptr` must point to a live [`HeapAtom`].
+    pub unsafe fn arena_slot(ptr: NonNull<u8>) -> NonZeroU32 {
+        let slot = &(*ptr.as_ptr().cast::<Header>()).slot;
+        let tag = slot.load(Ordering::Acquire);
+        if arena::has_slot(tag) {
+            return NonZeroU32::new_unchecked(tag);
+        }
+
+        let index = arena::insert(tag, ptr.as_ptr());
+        match slot.compare_exchange(tag, index.get(), Ordering::AcqRel, Ordering::Acquire) {
+            Ok(_) => index,
+            Err(theirs) => {
+                // Another thread gave it a slot first. Use theirs.
+                arena::remove(index);
+                NonZeroU32::new_unchecked(theirs)
+            }
+        }
+    }
+
+    /// Add a reference to this atom. Each call must be balanced by dropping a
+    /// [`HeapRc`] restored from it.
+    #[inline]
+    pub fn increment_count(&self) {
+        let refcount = &self.header.refcount;
+        if refcount.load(Ordering::Relaxed) >= IMMORTAL_THRESHOLD {
+            return;
+        }
+        // Relaxed is fine here, for the same reasons `Arc::clone` gives: new
+        // references can only be made from existing ones.
+        let old = refcount.fetch_add(1, Ordering::Relaxed);
+        if old + 1 >= IMMORTAL_THRESHOLD {
+            // Too many references to count. Rather than let the count wrap
+            // around and free a live atom, leak it.
+            refcount.store(IMMORTAL_REFCOUNT, Ordering::Relaxed);
+        }
+    }
+
+    /// Remove a reference to this atom. Returns `true` if it was the last
+    /// one, in which case the caller must free the atom.
+    #[inline]
+    fn decrement_count(&self) -> bool {
+        let refcount = &self.header.refcount;
+        if refcount.load(Ordering::Relaxed) >= IMMORTAL_THRESHOLD {
+            return false;
+        }
+        if refcount.fetch_sub(1, Ordering::Release) != 1 {
+            return false;
+        }
+        // Synchronize with every other reference's release, so that nothing
+        // they did to the atom gets reordered after the free.
+        atomic::fence(Ordering::Acquire);
+        true
+    }
+
+    /// Like [`HeapAtom::increment_count`], but with a plain load and store
+    /// instead of an atomic read-modify-write.
+    ///
+    /// # Safety
+    /// Every reference to this atom must live on the current thread.
+    #[inline]
+    pub unsafe fn increment_count_local(&self) {
+        let refcount = &self.header.refcount;
+        let old = refcount.load(Ordering::Relaxed);
+        if old >= IMMORTAL_THRESHOLD {
+            return;
+        }
+        let new = if old + 1 >= IMMORTAL_THRESHOLD {
+            IMMORTAL_REFCOUNT
+        } else {
+            old + 1
+        };
+        refcount.store(new, Ordering::Relaxed);
+    }
+
+    /// Pin this atom's refcount so it is never freed. The caller must hold a
+    /// reference, which is leaked.
+    #[inline]
+    pub fn make_immortal(&self) {
+        self.header
+            .refcount
+            .store(IMMORTAL_REFCOUNT, Ordering::Relaxed);
+    }
+
+    #[cfg(test)]
+    pub fn is_immortal(&self) -> bool {
+        self.header.refcount.load(Ordering::Relaxed) >= IMMORTAL_THRESHOLD
+    }
+
+    /// # Safety
+    /// `ptr` must point to a live atom with no references left.
+    unsafe fn dealloc(ptr: NonNull<HeapAtom>) {
+        let index = ptr.as_ref().header.slot.load(Ordering::Acquire);
+        if arena::has_slot(index) {
+            arena::remove(NonZeroU32::new_unchecked(index));
+        }
+        let layout = Layout::for_value(ptr.as_ref());
+        alloc::alloc::dealloc(ptr.as_ptr().cast(), layout);
     }
 
     #[inline]
@@ -267,17 +315,15 @@ impl HeapAtom {
         self.header.hash
     }
 
+    #[inline]
     pub const fn as_str(&self) -> &str {
-        unsafe {
-            let ptr = self.str_ptr();
-            core::str::from_utf8_unchecked(slice::from_raw_parts(ptr, self.header.len as usize))
-        }
+        &self.string
     }
 
     #[must_use]
     const fn get_layout(strlen: u32) -> Layout {
         // TODO: use pad_to_align(). See rust issue https://github.com/rust-lang/rust/issues/67521
-        let size_used = size_of::<SneakyArcInner<()>>() + size_of::<Header>() + strlen as usize;
+        let size_used = size_of::<Header>() + strlen as usize;
         let size = size_used.next_multiple_of(ALIGNMENT);
 
         debug_assert!(
@@ -297,15 +343,48 @@ impl HeapAtom {
         unsafe { Layout::from_size_align_unchecked(size, ALIGNMENT) }
     }
 
-    #[inline(always)]
-    const fn sizeof(strlen: u32) -> usize {
-        Self::get_layout(strlen).size()
+    /// Pointer to the start of the string. Unlike `self.string.as_ptr()`,
+    /// this may be used to read the padding after the string.
+    const unsafe fn str_ptr(&self) -> *const u8 {
+        (self as *const Self).cast::<u8>().add(size_of::<Header>())
     }
 
-    const unsafe fn str_ptr(&self) -> *const u8 {
-        (self as *const _ as *const u8).add(size_of::<Header>())
+    /// Compare the strings in two heap atoms a word at a time.
+    ///
+    /// Strings start on an [`ALIGNMENT`] boundary and are zero-padded up to
+    /// the end of their allocation, so we can compare whole `u64`s without
+    /// handling a tail.
+    pub fn str_eq(&self, other: &HeapAtom) -> bool {
+        if self.header.len != other.header.len {
+            return false;
+        }
+
+        let words = self.len().div_ceil(size_of::<u64>());
+        let (lhs, rhs) = unsafe {
+            debug_assert!(self.str_ptr().cast::<u64>().is_aligned());
+            debug_assert!(other.str_ptr().cast::<u64>().is_aligned());
+            (
+                slice::from_raw_parts(self.str_ptr().cast::<u64>(), words),
+                slice::from_raw_parts(other.str_ptr().cast::<u64>(), words),
+            )
+        };
+
+        // Most strings are short identifiers. For those, OR together the
+        // difference of every word and branch once at the end, instead of
+        // calling into `bcmp`. Long strings are better served by the vectorized
+        // `bcmp` loop.
+        if words <= SHORT_STR_WORDS {
+            lhs.iter().zip(rhs).fold(0, |diff, (l, r)| diff | (l ^ r)) == 0
+        } else {
+            lhs == rhs
+        }
     }
 }
+static_assertions::const_assert!(ALIGNMENT >= core::mem::align_of::<u64>());
+
+/// Strings up to this many words long are compared by [`HeapAtom::str_eq`]
+/// without calling `bcmp`.
+const SHORT_STR_WORDS: usize = 8;
 
 impl Hash for HeapAtom {
     fn hash<H: Hasher>(&self, state: &mut H) {
@@ -315,17 +394,136 @@ impl Hash for HeapAtom {
 
 impl PartialEq for HeapAtom {
     fn eq(&self, other: &Self) -> bool {
-        self.header.hash == other.header.hash && self.as_str() == other.as_str()
+        self.header.hash == other.header.hash && self.str_eq(other)
     }
 }
 impl Eq for HeapAtom {}
 
-pub(crate) fn str_hash(s: &str) -> u64 {
-    let mut hasher = FxHasher::default();
-    s.hash(&mut hasher);
-    hasher.finish()
+/// An owned reference to a [`HeapAtom`]. Works like an `Arc<HeapAtom>`, using
+/// the refcount in the atom's [`Header`].
+pub(crate) struct HeapRc {
+    ptr: NonNull<HeapAtom>,
+}
+
+// SAFETY: atoms are immutable after construction, and the refcount is atomic.
+unsafe impl Send for HeapRc {}
+unsafe impl Sync for HeapRc {}
+
+impl HeapRc {
+    /// # Safety
+    /// `ptr` must point to a live [`HeapAtom`], and the caller must own one of
+    /// its references.
+    #[inline]
+    pub const unsafe fn from_raw(ptr: NonNull<HeapAtom>) -> Self {
+        Self { ptr }
+    }
+
+    /// Give up ownership of this reference without decrementing the count.
+    /// Use [`HeapRc::from_raw`] to take it back.
+    #[inline]
+    #[must_use]
+    pub fn into_raw(self) -> NonNull<HeapAtom> {
+        ManuallyDrop::new(self).ptr
+    }
+
+    /// Like dropping this reference, but with a plain load and store instead
+    /// of an atomic read-modify-write.
+    ///
+    /// # Safety
+    /// Every reference to this atom must live on the current thread.
+    #[inline]
+    pub unsafe fn drop_local(self) {
+        let this = ManuallyDrop::new(self);
+        let refcount = &this.header.refcount;
+        match refcount.load(Ordering::Relaxed) {
+            1 => HeapAtom::dealloc(this.ptr),
+            count if count >= IMMORTAL_THRESHOLD => {}
+            count => refcount.store(count - 1, Ordering::Relaxed),
+        }
+    }
+
+    /// Give up ownership of this reference, and turn it into a heap-owned
+    /// [`TaggedValue`]. Use [`HeapAtom::restore_rc`] to take it back.
+    #[inline]
+    #[must_use]
+    pub fn into_tagged<S: AtomSize>(self) -> TaggedValue<S> {
+        let ptr = self.into_raw();
+        // SAFETY: we just gave up our reference to the tagged value, so the
+        // atom is alive. Allocations are aligned to `ALIGNMENT`.
+        unsafe { TaggedValue::new_heap(ptr.cast()) }
+    }
+
+    /// Whether this is the only reference to the atom. Immortal atoms are
+    /// never unique.
+    #[inline]
+    #[must_use]
+    pub fn is_unique(this: &Self) -> bool {
+        this.header.refcount.load(Ordering::Acquire) == 1
+    }
+
+    /// The address of the atom, for telling entries with the same hash apart.
+    #[inline]
+    #[must_use]
+    pub fn addr(this: &Self) -> usize {
+        this.ptr.as_ptr().cast::<u8>() as usize
+    }
+
+    #[cfg(test)]
+    #[must_use]
+    pub fn strong_count(this: &Self) -> u32 {
+        this.header.refcount.load(Ordering::Relaxed)
+    }
+}
+
+impl Deref for HeapRc {
+    type Target = HeapAtom;
+
+    #[inline]
+    fn deref(&self) -> &HeapAtom {
+        // SAFETY: we hold a reference, so the atom is alive.
+        unsafe { self.ptr.as_ref() }
+    }
 }
 
+impl Clone for HeapRc {
+    #[inline]
+    fn clone(&self) -> Self {
+        self.increment_count();
+        Self { ptr: self.ptr }
+    }
+}
+
+impl Drop for HeapRc {
+    #[inline]
+    fn drop(&mut self) {
+        if self.decrement_count() {
+            // SAFETY: that was the last reference.
+            unsafe { HeapAtom::dealloc(self.ptr) }
+        }
+    }
+}
+
+impl fmt::Debug for HeapRc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&**self, f)
+    }
+}
+
+impl Hash for HeapRc {
+    #[inline]
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Hash::hash(&**self, state);
+    }
+}
+
+impl PartialEq for HeapRc {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+impl Eq for HeapRc {}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -343,8 +541,8 @@ mod test {
 
         assert_eq!(atom.as_str(), atom2.as_str());
         assert!(!ptr::addr_eq(
-            atom.as_ref() as *const _,
-            atom2.as_ref() as *const _
+            &*atom as *const HeapAtom,
+            &*atom2 as *const HeapAtom
         ));
     }
 
@@ -372,18 +570,85 @@ mod test {
         assert_eq!(atom, atom);
     }
 
+    #[test]
+    fn test_str_eq() {
+        for len in [1, 7, 8, 9, 63, 64, 65, 200] {
+            let text = "x".repeat(len);
+            let a = HeapAtom::new(&text, None);
+            let b = HeapAtom::new(&text, None);
+            assert!(a.str_eq(&b));
+
+            let mut other = text.clone();
+            other.replace_range(len - 1.., "y");
+            assert!(!a.str_eq(&HeapAtom::new(&other, None)));
+            assert!(!a.str_eq(&HeapAtom::new(&text[1..], None)));
+        }
+    }
+
     #[test]
     fn test_counted_references() {
-        let normal_arc = Arc::new("foo");
         let foo = HeapAtom::new("foo", None);
-        let empty = unsafe { HeapAtom::zero_sized() };
+        assert_eq!(HeapRc::strong_count(&foo), 1);
+
+        let foo2 = foo.clone();
+        assert_eq!(HeapRc::strong_count(&foo), 2);
+        assert!(ptr::eq(&*foo, &*foo2));
+
+        drop(foo2);
+        assert_eq!(HeapRc::strong_count(&foo), 1);
+
+        let raw = foo.into_raw();
+        let foo = unsafe { HeapRc::from_raw(raw) };
+        assert_eq!(HeapRc::strong_count(&foo), 1);
+    }
+
+    #[test]
+    fn test_immortal() {
+        let foo = HeapAtom::new("foo", None);
+        assert!(!foo.is_immortal());
+        foo.make_immortal();
+        assert!(foo.is_immortal());
+
+        let foo2 = foo.clone();
+        drop(foo2);
+        assert_eq!(HeapRc::strong_count(&foo), IMMORTAL_REFCOUNT);
+
+        unsafe { foo.increment_count_local() };
+        let foo2 = unsafe { HeapRc::from_raw(foo.ptr) };
+        unsafe { foo2.drop_local() };
+        assert_eq!(HeapRc::strong_count(&foo), IMMORTAL_REFCOUNT);
+
+        // leaked on purpose, but don't make Miri report it
+        let raw = foo.into_raw();
+        unsafe { HeapAtom::dealloc(raw) };
+    }
 
-        // should have the same strong/weak ref count as a normally constructed
-        // Arc
-        assert_eq!(Arc::strong_count(&foo), Arc::strong_count(&normal_arc));
-        assert_eq!(Arc::weak_count(&foo), Arc::weak_count(&normal_arc));
+    #[test]
+    fn test_saturating_count() {
+        let foo = HeapAtom::new("foo", None);
+        foo.header
+            .refcount
+            .store(IMMORTAL_THRESHOLD - 2, Ordering::Relaxed);
+        foo.increment_count();
+        assert!(!foo.is_immortal());
+        foo.increment_count();
+        assert!(foo.is_immortal());
+        assert_eq!(HeapRc::strong_count(&foo), IMMORTAL_REFCOUNT);
+
+        let raw = foo.into_raw();
+        unsafe { HeapAtom::dealloc(raw) };
+    }
 
-        assert_eq!(Arc::strong_count(&empty), Arc::strong_count(&normal_arc));
-        assert_eq!(Arc::weak_count(&empty), Arc::weak_count(&normal_arc));
+    #[test]
+    fn test_layout() {
+        // header, then the string padded to the next word
+        for (s, padded) in [("", 0), ("a", 8), ("abcdefgh", 8), ("abcdefghi", 16)] {
+            let atom = HeapAtom::new(s, None);
+            assert_eq!(
+                core::mem::size_of_val::<HeapAtom>(&atom),
+                size_of::<Header>() + padded
+            );
+            assert_eq!(atom.as_str(), s);
+        }
     }
 }
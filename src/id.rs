@@ -0,0 +1,107 @@
+//! IDs of [`AtomStore`](crate::AtomStore)s, written into every heap atom a
+//! store makes.
+//!
+//! Two heap atoms with the same ID are only equal if they are the same
+//! pointer. So an ID can only go to a new store once no atom with it is left:
+//! a store gives its ID back when it is dropped if none of its entries are
+//! still in use, and otherwise the ID is retired for good.
+
+use alloc::vec::Vec;
+use core::num::NonZeroU32;
+use std::sync::Mutex;
+
+use crate::heap::HeapRc;
+
+/// The IDs every store gets its ID from.
+pub(crate) static STORE_IDS: StoreIds = StoreIds::new(u32::MAX);
+
+#[derive(Debug)]
+pub(crate) struct StoreIds {
+    list: Mutex<IdList>,
+}
+
+#[derive(Debug)]
+struct IdList {
+    /// Smallest ID that has never been handed out, or `None` once the
+    /// counter has run out.
+    next: Option<NonZeroU32>,
+    /// Largest ID that can be handed out.
+    last: u32,
+    /// IDs given back by dropped stores, ready to be reused.
+    free: Vec<NonZeroU32>,
+}
+
+impl StoreIds {
+    /// IDs `1..=last`.
+    pub const fn new(last: u32) -> Self {
+        Self {
+            list: Mutex::new(IdList {
+                next: Some(NonZeroU32::MIN),
+                last,
+                free: Vec::new(),
+            }),
+        }
+    }
+
+    /// An ID that no live atom has, or `None` if they have all been handed
+    /// out.
+    pub fn acquire(&self) -> Option<NonZeroU32> {
+        let mut list = self
+            .list
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+        if let Some(id) = list.free.pop() {
+            return Some(id);
+        }
+        let id = list.next.filter(|id| id.get() <= list.last)?;
+        list.next = id.checked_add(1);
+        Some(id)
+    }
+
+    /// Give back `id`, which was [`acquire`](StoreIds::acquire)d from here.
+    /// No atom with it may be left, or atoms for the same string could
+    /// compare unequal.
+    fn release(&self, id: NonZeroU32) {
+        self.list
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+            .free
+            .push(id);
+    }
+
+    /// Give back the ID of a store that is being dropped, unless one of its
+    /// `entries` with that ID is still in use outside of it.
+    pub fn release_unused<'a>(
+        &self,
+        id: NonZeroU32,
+        mut entries: impl Iterator<Item = &'a HeapRc>,
+    ) {
+        let unused = entries.all(|entry| entry.store_id() != Some(id) || HeapRc::is_unique(entry));
+        if unused {
+            self.release(id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_exhaustion() {
+        let ids = StoreIds::new(3);
+        let acquired: Vec<_> = (0..3).map(|_| ids.acquire().unwrap().get()).collect();
+        assert_eq!(acquired, [1, 2, 3]);
+        assert_eq!(ids.acquire(), None);
+
+        ids.release(NonZeroU32::new(2).unwrap());
+        assert_eq!(ids.acquire().map(NonZeroU32::get), Some(2));
+        assert_eq!(ids.acquire(), None);
+
+        // the counter never wraps around to 0
+        let ids = StoreIds::new(u32::MAX);
+        ids.list.lock().unwrap().next = NonZeroU32::new(u32::MAX);
+        assert_eq!(ids.acquire().map(NonZeroU32::get), Some(u32::MAX));
+        assert_eq!(ids.acquire(), None);
+    }
+}
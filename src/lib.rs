@@ -18,15 +18,33 @@
 extern crate assert_unchecked;
 extern crate alloc;
 
+mod arena;
+mod builder;
+mod config;
+mod frozen;
+mod hash;
 mod heap;
+mod id;
+mod local;
+#[cfg(any(test, feature = "fuzzing"))]
+#[doc(hidden)]
+pub mod model;
+mod normalize;
+mod ops;
+mod packed;
+#[cfg(feature = "rayon")]
+mod par;
+mod shared;
+mod size;
 mod store;
 mod tags;
 #[cfg(test)]
 mod test;
+mod visit;
 
-use core::{hash::Hash, marker::PhantomData, ops::Deref};
+use core::{fmt, hash::Hash, marker::PhantomData, mem::ManuallyDrop, ops::Deref};
 
-use alloc::{borrow::Cow, sync::Arc};
+use alloc::borrow::Cow;
 use heap::HeapAtom;
 use store::atom;
 use tags::{Tag, TaggedValue, MAX_INLINE_LEN};
@@ -35,15 +53,38 @@ use alloc::string::String;
 
 pub(crate) const ALIGNMENT: usize = 8;
 
-pub use store::AtomStore;
-
+pub use builder::AtomBuilder;
+pub use config::{AtomStoreBuilder, IdPolicy, StoreError};
+pub use frozen::FrozenAtomStore;
+pub use hash::AtomHasher;
+pub use local::{LocalAtom, LocalAtomStore};
+pub use normalize::{Normalization, NormalizedAtom, NormalizedAtomStore};
+pub use packed::AtomStr;
+pub use shared::{AtomCache, CacheStats, SharedAtomStore};
+pub use size::{AtomSize, Bits128, Bits32, Bits64, DefaultSize, Packed64, Utf8Size};
+pub use store::{AtomStore, Checkpoint};
+pub use visit::{AtomVisitor, VisitAtoms};
+
+/// An interned string.
+///
+/// `S` picks how big the atom is, and so how long a string it can hold
+/// inline. See [`AtomSize`].
 #[derive(Debug)]
-pub struct Atom<'a> {
-    inner: TaggedValue,
+pub struct Atom<'a, S: AtomSize = DefaultSize> {
+    inner: TaggedValue<S>,
     marker: PhantomData<&'a ()>,
 }
-unsafe impl Send for Atom<'static> {}
-unsafe impl Sync for Atom<'static> {}
+unsafe impl<S: AtomSize> Send for Atom<'static, S> {}
+unsafe impl<S: AtomSize> Sync for Atom<'static, S> {}
+
+/// A 16-byte [`Atom`].
+pub type Atom128<'a> = Atom<'a, Bits128>;
+/// An 8-byte [`Atom`].
+pub type Atom64<'a> = Atom<'a, Bits64>;
+/// An 8-byte [`Atom`] that packs identifiers. See [`Packed64`].
+pub type AtomPacked64<'a> = Atom<'a, Packed64>;
+/// A 4-byte [`Atom`].
+pub type Atom32<'a> = Atom<'a, Bits32>;
 
 impl Atom<'static> {
     pub fn new<S: AsRef<str>>(s: S) -> Self {
@@ -56,9 +97,8 @@ impl Atom<'static> {
     }
 
     pub const fn empty() -> Self {
-        const EMPTY: TaggedValue = TaggedValue::new_inline(0);
         Self {
-            inner: EMPTY,
+            inner: TaggedValue::EMPTY,
             marker: PhantomData,
         }
     }
@@ -70,30 +110,53 @@ impl Atom<'static> {
         assert!(s.len() <= MAX_INLINE_LEN, "Cannot inline string '{s}' because its length exceeds the maximum inlineable length of {MAX_INLINE_LEN}");
         Self::new_inline_impl(s)
     }
+}
 
-    pub(crate) fn new_inline_impl(s: &str) -> Self {
-        let len = s.len();
-        debug_assert!(len <= MAX_INLINE_LEN);
-        #[allow(clippy::cast_possible_truncation)]
-        let mut value = TaggedValue::new_inline(len as u8);
-        unsafe {
-            value.as_bytes_mut()[..len].copy_from_slice(s.as_bytes());
-        }
+impl<S: AtomSize> Atom<'static, S> {
+    /// Like [`Atom::new`], for atoms of any size.
+    ///
+    /// ```
+    /// use stern::{Atom32, Atom128};
+    ///
+    /// let small = Atom32::intern("foo");
+    /// let big = Atom128::intern("foo");
+    /// assert_eq!(small, "foo");
+    /// assert_eq!(small.resize(), big);
+    /// ```
+    pub fn intern<T: AsRef<str>>(s: T) -> Self {
+        let s = s.as_ref();
+        Self::new_small(s).unwrap_or_else(|| atom(s))
+    }
 
-        Self {
-            inner: value,
+    /// Store `s` inside the atom if it fits, either as UTF-8 or packed.
+    #[inline]
+    pub(crate) fn new_small(s: &str) -> Option<Self> {
+        let inner = if s.len() <= S::MAX_INLINE_LEN {
+            return Some(Self::new_inline_impl(s));
+        } else if s.len() <= S::MAX_PACKED_LEN {
+            TaggedValue::new_packed(s)?
+        } else {
+            return None;
+        };
+        Some(Self {
+            inner,
             marker: PhantomData,
-        }
+        })
+    }
+
+    #[inline]
+    pub(crate) fn new_inline_impl(s: &str) -> Self {
+        Self::new_inline_parts(&[s], s.len())
     }
 }
 
-impl<'a> Atom<'a> {
+impl<'a, S: AtomSize> Atom<'a, S> {
     #[must_use]
     #[allow(clippy::missing_panics_doc)]
-    pub const fn len(&self) -> usize {
+    pub fn len(&self) -> usize {
         match self.inner.tag() {
             Tag::HeapOwned => unsafe { HeapAtom::deref_from(self.inner) }.len(),
-            Tag::Inline => (self.inner.tag_byte() >> Tag::INLINE_LEN_OFFSET) as usize,
+            Tag::Inline => self.inner.len(),
             Tag::Static => {
                 panic!("TODO: Atom#len() for Tag::Static")
             }
@@ -101,10 +164,39 @@ impl<'a> Atom<'a> {
     }
 
     #[inline]
-    pub const fn is_empty(&self) -> bool {
+    pub fn is_empty(&self) -> bool {
         self.len() == 0
     }
 
+    /// Whether this atom's text is stored in the atom itself, rather than in
+    /// a heap allocation.
+    #[inline]
+    pub fn is_inline(&self) -> bool {
+        self.inner.tag().is_inline()
+    }
+
+    /// Convert this atom to another size.
+    ///
+    /// Heap atoms keep sharing their allocation, so this never copies a long
+    /// string.
+    #[must_use]
+    pub fn resize<T: AtomSize>(self) -> Atom<'a, T> {
+        if !self.is_heap() {
+            return Atom::intern(self.to_str());
+        }
+        if let Some(small) = Atom::new_small(&self.to_str()) {
+            return small;
+        }
+
+        let this = ManuallyDrop::new(self);
+        // SAFETY: we own `this`'s reference, and never drop it.
+        let rc = unsafe { HeapAtom::restore_rc(this.inner) };
+        Atom {
+            inner: rc.into_tagged(),
+            marker: PhantomData,
+        }
+    }
+
     #[allow(clippy::missing_panics_doc)]
     fn get_hash(&self) -> u64 {
         match self.inner.tag() {
@@ -121,9 +213,21 @@ impl<'a> Atom<'a> {
         self.inner.tag().is_heap_owned()
     }
 
+    /// The text of this atom. Unlike [`Atom::as_str`], this works for atoms
+    /// of every size, including [`Packed64`].
     #[must_use]
+    #[inline]
+    pub fn to_str(&self) -> AtomStr<'_> {
+        if self.is_inline() && self.inner.is_packed() {
+            self.inner.unpack()
+        } else {
+            AtomStr::borrowed(self.as_utf8())
+        }
+    }
+
+    /// Text of an atom that isn't packed.
     #[allow(clippy::missing_panics_doc)]
-    pub fn as_str(&self) -> &str {
+    fn as_utf8(&self) -> &str {
         match self.inner.tag() {
             Tag::HeapOwned => unsafe { HeapAtom::deref_from(self.inner) }.as_str(),
             Tag::Inline => unsafe {
@@ -136,11 +240,51 @@ impl<'a> Atom<'a> {
         }
     }
 
+    /// Returns this atom with all ASCII letters lowercased.
+    ///
+    /// If the atom is already lowercase, this is a cheap clone and does not
+    /// allocate.
+    #[must_use]
+    pub fn to_ascii_lowercase(&self) -> Self {
+        let text = self.to_str();
+        if !text.bytes().any(|b| b.is_ascii_uppercase()) {
+            return self.clone();
+        }
+
+        if self.is_inline() && !self.inner.is_packed() {
+            let mut inner = self.inner;
+            unsafe { inner.as_bytes_mut() }.make_ascii_lowercase();
+            Self {
+                inner,
+                marker: PhantomData,
+            }
+        } else {
+            Atom::intern(text.to_ascii_lowercase())
+        }
+    }
+
+    /// Leak this atom, so that it lives until the program exits.
+    ///
+    /// Heap atoms become immortal: their refcount is pinned, so cloning and
+    /// dropping them no longer touches it. This is worthwhile for atoms that
+    /// get cloned very often, like common keywords.
+    #[must_use]
+    pub fn leak(self) -> Atom<'static, S> {
+        let this = ManuallyDrop::new(self);
+        if this.is_heap() {
+            unsafe { HeapAtom::deref_from(this.inner) }.make_immortal();
+        }
+
+        Atom {
+            inner: this.inner,
+            marker: PhantomData,
+        }
+    }
+
     #[must_use]
     unsafe fn alias(&self) -> Self {
         debug_assert!(self.is_heap());
-        let heap_atom = HeapAtom::deref_from(self.inner);
-        Arc::increment_strong_count(heap_atom as *const _);
+        HeapAtom::deref_from(self.inner).increment_count();
 
         Self {
             inner: self.inner,
@@ -149,7 +293,15 @@ impl<'a> Atom<'a> {
     }
 }
 
-impl<'a> Clone for Atom<'a> {
+impl<S: Utf8Size> Atom<'_, S> {
+    #[must_use]
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        self.as_utf8()
+    }
+}
+
+impl<S: AtomSize> Clone for Atom<'_, S> {
     #[allow(clippy::missing_panics_doc)]
     fn clone(&self) -> Self {
         match self.inner.tag() {
@@ -190,7 +342,17 @@ impl From<Cow<'_, str>> for Atom<'static> {
         atom(&value)
     }
 }
-impl Deref for Atom<'_> {
+impl<S: AtomSize> Default for Atom<'static, S> {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            inner: TaggedValue::EMPTY,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<S: Utf8Size> Deref for Atom<'_, S> {
     type Target = str;
 
     #[inline]
@@ -199,7 +361,14 @@ impl Deref for Atom<'_> {
     }
 }
 
-impl Hash for Atom<'_> {
+impl<S: AtomSize> fmt::Display for Atom<'_, S> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&*self.to_str(), f)
+    }
+}
+
+impl<S: AtomSize> Hash for Atom<'_, S> {
     #[inline]
     fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
         state.write_u64(self.get_hash());
@@ -207,9 +376,9 @@ impl Hash for Atom<'_> {
 }
 
 #[cfg(feature = "nohash-hasher")]
-impl nohash_hasher::IsEnabled for Atom<'_> {}
+impl<S: AtomSize> nohash_hasher::IsEnabled for Atom<'_, S> {}
 
-impl PartialEq for Atom<'_> {
+impl<S: AtomSize> PartialEq for Atom<'_, S> {
     #[inline(never)]
     fn eq(&self, other: &Self) -> bool {
         if self.inner == other.inner {
@@ -227,57 +396,53 @@ impl PartialEq for Atom<'_> {
         if self.is_heap() && other.is_heap() {
             let self_heap = unsafe { HeapAtom::deref_from(self.inner) };
             let other_heap = unsafe { HeapAtom::deref_from(other.inner) };
-            // If the store is the same, the same string has same `unsafe_data``
-            match (&self_heap.store_id(), &other_heap.store_id()) {
-                (Some(this_store), Some(other_store)) => {
-                    if this_store == other_store {
-                        return false;
-                    }
-                }
-                (None, None) => {
-                    return false;
-                }
-                _ => {}
+            // If the store is the same, the same string has same `unsafe_data``.
+            // Anonymous stores have no ID, so their atoms compare strings.
+            let store_id = self_heap.store_id();
+            if store_id.is_some() && store_id == other_heap.store_id() {
+                return false;
             }
+
+            return self_heap.str_eq(other_heap);
         }
 
-        self.as_str() == self.as_str()
+        *self.to_str() == *other.to_str()
     }
 }
-impl Eq for Atom<'_> {}
+impl<S: AtomSize> Eq for Atom<'_, S> {}
 
-impl PartialEq<str> for Atom<'_> {
+impl<S: AtomSize> PartialEq<str> for Atom<'_, S> {
     #[inline]
     fn eq(&self, other: &str) -> bool {
-        self.as_str() == other
+        *self.to_str() == *other
     }
 }
 
-impl PartialEq<&'_ str> for Atom<'_> {
+impl<S: AtomSize> PartialEq<&'_ str> for Atom<'_, S> {
     #[inline]
     fn eq(&self, other: &&str) -> bool {
-        self.as_str() == *other
+        *self.to_str() == **other
     }
 }
 
-impl PartialEq<Atom<'_>> for str {
+impl<S: AtomSize> PartialEq<Atom<'_, S>> for str {
     #[inline]
-    fn eq(&self, other: &Atom<'_>) -> bool {
-        self == other.as_str()
+    fn eq(&self, other: &Atom<'_, S>) -> bool {
+        *self == *other.to_str()
     }
 }
 
-impl AsRef<str> for Atom<'_> {
+impl<S: Utf8Size> AsRef<str> for Atom<'_, S> {
     #[inline]
     fn as_ref(&self) -> &str {
         self.as_str()
     }
 }
 
-impl Drop for Atom<'_> {
+impl<S: AtomSize> Drop for Atom<'_, S> {
     fn drop(&mut self) {
         if self.is_heap() {
-            let heap_atom = unsafe { HeapAtom::restore_arc(self.inner) };
+            let heap_atom = unsafe { HeapAtom::restore_rc(self.inner) };
             drop(heap_atom);
         }
     }
@@ -285,33 +450,34 @@ impl Drop for Atom<'_> {
 
 #[cfg(feature = "serde")]
 mod serde_impls {
-    use super::Atom;
+    use super::{Atom, AtomSize};
+    use core::marker::PhantomData;
     use serde::{de, Deserialize, Serialize};
     use std::fmt;
 
-    impl Serialize for Atom<'_> {
-        fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    impl<S: AtomSize> Serialize for Atom<'_, S> {
+        fn serialize<Ser>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error>
         where
-            S: serde::Serializer,
+            Ser: serde::Serializer,
         {
-            serializer.serialize_str(self.as_str())
+            serializer.serialize_str(&self.to_str())
         }
     }
 
-    impl<'de> Deserialize<'de> for Atom<'static> {
+    impl<'de, S: AtomSize> Deserialize<'de> for Atom<'static, S> {
         fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
         where
             D: serde::Deserializer<'de>,
         {
-            deserializer.deserialize_str(AtomVisitor)
+            deserializer.deserialize_str(AtomVisitor(PhantomData))
         }
     }
 
     #[derive(Clone, Copy, Debug, Default)]
-    struct AtomVisitor;
+    struct AtomVisitor<S>(PhantomData<S>);
 
-    impl<'de> de::Visitor<'de> for AtomVisitor {
-        type Value = Atom<'static>;
+    impl<'de, S: AtomSize> de::Visitor<'de> for AtomVisitor<S> {
+        type Value = Atom<'static, S>;
 
         fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
             formatter.write_str("a borrowed string")
@@ -321,7 +487,7 @@ mod serde_impls {
         where
             E: de::Error,
         {
-            Ok(Atom::new(v))
+            Ok(Atom::intern(v))
         }
         fn visit_char<E>(self, v: char) -> Result<Self::Value, E>
         where
@@ -329,7 +495,7 @@ mod serde_impls {
         {
             let mut buf = [0u8; 4];
             let s = v.encode_utf8(&mut buf);
-            Ok(Atom::new_inline_impl(s))
+            Ok(Atom::intern(s))
         }
     }
 }
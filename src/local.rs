@@ -0,0 +1,206 @@
+use core::cell::RefCell;
+use core::hash::{Hash, Hasher};
+use core::marker::PhantomData;
+use core::mem::ManuallyDrop;
+use core::{fmt, ops::Deref};
+
+use crate::heap::HeapAtom;
+use crate::{Atom, AtomStore};
+
+/// Makes a type `!Send` and `!Sync`.
+type NotSend = PhantomData<*const ()>;
+
+/// An [`AtomStore`] whose atoms never leave the current thread.
+///
+/// Since no other thread can see its atoms, the [`LocalAtom`]s it creates
+/// update their refcounts with plain loads and stores instead of atomic
+/// read-modify-writes. This makes cloning and dropping heap atoms cheaper.
+#[derive(Debug, Default)]
+pub struct LocalAtomStore {
+    // Never hand out `Atom`s from this store. They are `Send`, and could race
+    // with our non-atomic refcount updates.
+    store: AtomStore<'static>,
+    marker: NotSend,
+}
+
+impl LocalAtomStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn atom<S: AsRef<str>>(&mut self, s: S) -> LocalAtom {
+        // SAFETY: the atom comes from our store, which can't leave this thread.
+        unsafe { LocalAtom::from_atom(self.store.atom(s)) }
+    }
+}
+
+/// A single-threaded [`Atom`].
+///
+/// `LocalAtom`s are `!Send` and `!Sync`, so cloning and dropping them does not
+/// need atomic instructions. They are created by a [`LocalAtomStore`], or by
+/// [`LocalAtom::new`] using this thread's global [`LocalAtomStore`].
+pub struct LocalAtom {
+    atom: ManuallyDrop<Atom<'static>>,
+    marker: NotSend,
+}
+
+impl LocalAtom {
+    pub fn new<S: AsRef<str>>(s: S) -> Self {
+        thread_local! {
+            static GLOBAL_DATA: RefCell<LocalAtomStore> = RefCell::default();
+        }
+
+        GLOBAL_DATA.with(|global| global.borrow_mut().atom(s))
+    }
+
+    /// # Safety
+    /// If `atom` is on the heap, every other reference to it must be a
+    /// [`LocalAtom`] or belong to a [`LocalAtomStore`] on this thread.
+    #[inline]
+    unsafe fn from_atom(atom: Atom<'static>) -> Self {
+        Self {
+            atom: ManuallyDrop::new(atom),
+            marker: PhantomData,
+        }
+    }
+
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        self.atom.as_str()
+    }
+}
+
+impl Clone for LocalAtom {
+    #[inline]
+    fn clone(&self) -> Self {
+        let atom = if self.atom.is_heap() {
+            // SAFETY: all references to this atom are on this thread.
+            unsafe { HeapAtom::deref_from(self.atom.inner).increment_count_local() };
+            Atom {
+                inner: self.atom.inner,
+                marker: PhantomData,
+            }
+        } else {
+            Atom::clone(&self.atom)
+        };
+
+        Self {
+            atom: ManuallyDrop::new(atom),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl Drop for LocalAtom {
+    #[inline]
+    fn drop(&mut self) {
+        if self.atom.is_heap() {
+            // SAFETY: all references to this atom are on this thread, and we
+            // never use `self.atom` again.
+            unsafe { HeapAtom::restore_rc(self.atom.inner).drop_local() };
+        } else {
+            // SAFETY: `self.atom` is never used again.
+            unsafe { ManuallyDrop::drop(&mut self.atom) };
+        }
+    }
+}
+
+impl Deref for LocalAtom {
+    type Target = str;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for LocalAtom {
+    #[inline]
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl fmt::Debug for LocalAtom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&*self.atom, f)
+    }
+}
+
+impl fmt::Display for LocalAtom {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.as_str(), f)
+    }
+}
+
+impl Hash for LocalAtom {
+    #[inline]
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.atom.hash(state);
+    }
+}
+
+#[cfg(feature = "nohash-hasher")]
+impl nohash_hasher::IsEnabled for LocalAtom {}
+
+impl PartialEq for LocalAtom {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        *self.atom == *other.atom
+    }
+}
+impl Eq for LocalAtom {}
+
+impl PartialEq<Atom<'_>> for LocalAtom {
+    #[inline]
+    fn eq(&self, other: &Atom<'_>) -> bool {
+        *self.atom == *other
+    }
+}
+
+impl PartialEq<str> for LocalAtom {
+    #[inline]
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&'_ str> for LocalAtom {
+    #[inline]
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn local_atoms() {
+        let long = "a string long enough to be stored on the heap";
+        let mut store = LocalAtomStore::new();
+        let a = store.atom(long);
+        let b = a.clone();
+        assert!(a.atom.is_heap());
+        assert_eq!(a.atom.inner, b.atom.inner);
+        assert_eq!(a, b);
+        assert_eq!(a, long);
+        assert_eq!(a, Atom::new(long));
+        assert_eq!(a.atom.get_hash(), Atom::new(long).get_hash());
+
+        // atoms outlive their store
+        drop(store);
+        drop(a);
+        assert_eq!(b, long);
+
+        let c = LocalAtom::new(long);
+        assert_eq!(c.atom.inner, LocalAtom::new(long).atom.inner);
+        assert_eq!(c, b);
+
+        let short = LocalAtom::new("foo");
+        assert!(!short.atom.is_heap());
+        assert_eq!(short.clone(), "foo");
+    }
+}
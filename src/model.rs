@@ -0,0 +1,136 @@
+//! A reference model for differential testing.
+//!
+//! Keeps atoms alongside the plain [`String`]s they should be equal to, and
+//! checks that atoms compare and hash the way their strings do. Shared by the
+//! proptest suite and the `cargo fuzz` harnesses, so that both check the same
+//! things. Only available with the `fuzzing` feature, and not part of the
+//! public API.
+
+use core::hash::{Hash, Hasher};
+use std::collections::hash_map::DefaultHasher;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use crate::{Atom, AtomStore};
+
+/// Number of stores a [`Model`] interns into.
+pub const STORES: usize = 3;
+/// Cap on live atoms, so that checking every pair stays cheap.
+pub const MAX_ATOMS: usize = 64;
+
+/// Operations every differential test knows how to apply.
+#[derive(Debug, Clone)]
+pub enum Op {
+    /// Create an atom using the thread-local global store.
+    New(String),
+    /// Create an atom in one of the [`AtomStore`]s.
+    Intern(usize, String),
+    Clone(usize),
+    Drop(usize),
+    /// Drop one of the stores, keeping the atoms it created alive.
+    DropStore(usize),
+}
+
+/// Atoms alongside the strings they should be equal to.
+pub struct Model {
+    stores: Vec<Option<AtomStore<'static>>>,
+    atoms: Vec<(Atom<'static>, String)>,
+}
+
+impl Default for Model {
+    fn default() -> Self {
+        Self {
+            stores: (0..STORES).map(|_| Some(AtomStore::default())).collect(),
+            atoms: Vec::new(),
+        }
+    }
+}
+
+impl Model {
+    /// One of the stores, made anew if it was dropped.
+    pub fn store(&mut self, index: usize) -> &mut AtomStore<'static> {
+        self.stores[index % STORES].get_or_insert_with(AtomStore::default)
+    }
+
+    /// One of the live atoms, or `None` if there are none.
+    pub fn get(&self, index: usize) -> Option<&(Atom<'static>, String)> {
+        if self.atoms.is_empty() {
+            None
+        } else {
+            Some(&self.atoms[index % self.atoms.len()])
+        }
+    }
+
+    /// Keep `atom` alive, unless there are too many already.
+    ///
+    /// # Panics
+    /// If `atom` isn't equal to `expected`.
+    pub fn push(&mut self, atom: Atom<'static>, expected: String) {
+        assert_eq!(atom.as_str(), expected);
+        if self.atoms.len() < MAX_ATOMS {
+            self.atoms.push((atom, expected));
+        }
+    }
+
+    pub fn apply(&mut self, op: Op) {
+        match op {
+            Op::New(s) => self.push(Atom::new(&s), s),
+            Op::Intern(store, s) => {
+                let atom = self.store(store).atom(&s);
+                self.push(atom, s);
+            }
+            Op::Clone(i) => {
+                let Some((atom, s)) = self.get(i) else { return };
+                let (atom, s) = (atom.clone(), s.clone());
+                self.push(atom, s);
+            }
+            Op::Drop(i) => {
+                if !self.atoms.is_empty() {
+                    let len = self.atoms.len();
+                    drop(self.atoms.swap_remove(i % len));
+                }
+            }
+            Op::DropStore(store) => drop(self.stores[store % STORES].take()),
+        }
+    }
+
+    /// Check every live atom against its string, and every pair of them
+    /// against each other.
+    ///
+    /// # Panics
+    /// If an atom doesn't behave like its string.
+    pub fn check(&self) {
+        check(&self.atoms);
+    }
+}
+
+/// Check `atoms` against their strings, and every pair of them against each
+/// other.
+///
+/// # Panics
+/// If an atom doesn't behave like its string.
+pub fn check(atoms: &[(Atom<'static>, String)]) {
+    for (atom, s) in atoms {
+        assert_eq!(atom.as_str(), s);
+        assert_eq!(&**atom, s.as_str());
+        assert_eq!(atom.len(), s.len());
+        assert_eq!(atom.is_empty(), s.is_empty());
+        assert_eq!(atom, s.as_str());
+    }
+
+    for (a, s) in atoms {
+        for (b, t) in atoms {
+            assert_eq!(a == b, s == t, "{a:?} == {b:?} should be {}", s == t);
+            if a == b {
+                assert_eq!(hash_of(a), hash_of(b), "equal atoms must hash the same");
+            }
+        }
+    }
+}
+
+pub fn hash_of<T: Hash + ?Sized>(value: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
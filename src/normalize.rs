@@ -0,0 +1,157 @@
+use alloc::borrow::Cow;
+use core::hash::{Hash, Hasher};
+use core::ops::Deref;
+use core::ptr;
+
+use crate::{Atom, AtomStore};
+
+/// How a [`NormalizedAtomStore`] folds strings before interning them.
+#[derive(Debug, Clone, Copy)]
+pub enum Normalization {
+    /// Lowercase ASCII letters, leaving all other characters untouched. Good
+    /// for HTML tag names, CSS properties and SQL identifiers.
+    AsciiLowercase,
+    /// Full Unicode default case folding.
+    #[cfg(feature = "unicode")]
+    CaseFold,
+    /// Unicode Normalization Form C (canonical composition).
+    #[cfg(feature = "unicode")]
+    Nfc,
+    /// A user-provided normalization function.
+    Custom(fn(&str) -> Cow<'_, str>),
+}
+
+impl Normalization {
+    /// Normalize `s`, borrowing it when it is already in normal form.
+    pub fn apply<'s>(&self, s: &'s str) -> Cow<'s, str> {
+        match self {
+            Self::AsciiLowercase => {
+                if s.bytes().any(|b| b.is_ascii_uppercase()) {
+                    Cow::Owned(s.to_ascii_lowercase())
+                } else {
+                    Cow::Borrowed(s)
+                }
+            }
+            #[cfg(feature = "unicode")]
+            Self::CaseFold => {
+                let folded = caseless::default_case_fold_str(s);
+                if folded == s {
+                    Cow::Borrowed(s)
+                } else {
+                    Cow::Owned(folded)
+                }
+            }
+            #[cfg(feature = "unicode")]
+            Self::Nfc => {
+                use unicode_normalization::{is_nfc_quick, IsNormalized, UnicodeNormalization};
+                if matches!(is_nfc_quick(s.chars()), IsNormalized::Yes) {
+                    Cow::Borrowed(s)
+                } else {
+                    Cow::Owned(s.nfc().collect())
+                }
+            }
+            Self::Custom(normalize) => normalize(s),
+        }
+    }
+}
+
+/// An [`AtomStore`] that interns strings under a [`Normalization`].
+///
+/// Every [`NormalizedAtom`] it produces keeps the original spelling alongside
+/// the normalized atom. Since both come from the same store, comparing two
+/// normalized atoms is a pointer comparison.
+#[derive(Debug)]
+pub struct NormalizedAtomStore {
+    store: AtomStore<'static>,
+    normalization: Normalization,
+}
+
+impl NormalizedAtomStore {
+    pub fn new(normalization: Normalization) -> Self {
+        Self {
+            store: AtomStore::default(),
+            normalization,
+        }
+    }
+
+    #[inline]
+    pub fn normalization(&self) -> Normalization {
+        self.normalization
+    }
+
+    pub fn atom<S: AsRef<str>>(&mut self, s: S) -> NormalizedAtom {
+        let s = s.as_ref();
+        let original = self.store.atom(s);
+        let normalized = match self.normalization.apply(s) {
+            // a custom normalization can borrow part of `s`, like `s.trim()`
+            Cow::Borrowed(normalized) if ptr::eq(normalized, s) => original.clone(),
+            Cow::Borrowed(normalized) => self.store.atom(normalized),
+            Cow::Owned(normalized) => self.store.atom(normalized),
+        };
+
+        NormalizedAtom {
+            original,
+            normalized,
+        }
+    }
+}
+
+/// An [`Atom`] paired with its normalized form.
+///
+/// Equality and hashing use the normalized form, while [`Deref`] and
+/// [`NormalizedAtom::as_str`] give back the original spelling.
+#[derive(Debug, Clone)]
+pub struct NormalizedAtom {
+    original: Atom<'static>,
+    normalized: Atom<'static>,
+}
+
+impl NormalizedAtom {
+    /// The string as it was originally spelled.
+    #[inline]
+    pub fn original(&self) -> &Atom<'static> {
+        &self.original
+    }
+
+    /// The string after normalization.
+    #[inline]
+    pub fn normalized(&self) -> &Atom<'static> {
+        &self.normalized
+    }
+
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        self.original.as_str()
+    }
+
+    /// Compare normalized forms. This is O(1) for atoms from the same
+    /// [`NormalizedAtomStore`].
+    #[inline]
+    pub fn eq_ignore_case(&self, other: &Self) -> bool {
+        self.normalized == other.normalized
+    }
+}
+
+impl Deref for NormalizedAtom {
+    type Target = str;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        self.as_str()
+    }
+}
+
+impl PartialEq for NormalizedAtom {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.eq_ignore_case(other)
+    }
+}
+impl Eq for NormalizedAtom {}
+
+impl Hash for NormalizedAtom {
+    #[inline]
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.normalized.hash(state);
+    }
+}
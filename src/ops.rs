@@ -0,0 +1,157 @@
+//! String operations that produce [`Atom`]s. Apart from
+//! [`atom_format!`](crate::atom_format) on long output, they don't go through
+//! a temporary [`String`].
+
+use alloc::vec::Vec;
+use core::{
+    fmt,
+    marker::PhantomData,
+    ops::{Bound, RangeBounds},
+};
+
+use crate::builder::AtomBuilder;
+use crate::hash::parts_hash;
+use crate::packed;
+use crate::size::AtomSize;
+use crate::store::atom_from_parts;
+use crate::tags::TaggedValue;
+use crate::Atom;
+
+/// Create an [`Atom`] from a format string, like [`format!`] does for
+/// [`String`]s.
+///
+/// ```
+/// use stern::{atom_format, Atom};
+///
+/// let name = Atom::new("foo");
+/// assert_eq!(atom_format!("{name}$1"), "foo$1");
+/// ```
+#[macro_export]
+macro_rules! atom_format {
+    ($($arg:tt)*) => {
+        $crate::Atom::from_fmt(::core::format_args!($($arg)*))
+    };
+}
+
+impl Atom<'static> {
+    /// Create an atom from the concatenation of `parts`.
+    ///
+    /// Short results are written straight into an inline atom. Longer ones
+    /// are hashed piece by piece and copied directly into the interned
+    /// allocation, so no temporary [`String`] is created.
+    ///
+    /// ```
+    /// use stern::Atom;
+    ///
+    /// assert_eq!(Atom::concat(&["foo", "$", "bar"]), "foo$bar");
+    /// ```
+    pub fn concat(parts: &[&str]) -> Self {
+        Self::from_parts(parts)
+    }
+
+    /// Create an atom from formatted text. You probably want to use
+    /// [`atom_format!`] instead.
+    ///
+    /// The text is written into an [`AtomBuilder`], and hashed as it is
+    /// written. Text short enough to be inlined stays on the stack, but longer
+    /// text is collected in a [`String`] before being interned, since its
+    /// length isn't known until formatting is done.
+    ///
+    /// # Panics
+    ///
+    /// If a formatting trait implementation returns an error.
+    pub fn from_fmt(args: fmt::Arguments<'_>) -> Self {
+        if let Some(s) = args.as_str() {
+            return Self::new(s);
+        }
+
+        let mut builder = AtomBuilder::new();
+        fmt::write(&mut builder, args)
+            .expect("a formatting trait implementation returned an error when the underlying stream did not");
+        builder.finish()
+    }
+}
+
+impl<S: AtomSize> Atom<'static, S> {
+    /// [`Atom::concat`], for atoms of any size.
+    pub(crate) fn from_parts(parts: &[&str]) -> Self {
+        let len = parts.iter().map(|part| part.len()).sum();
+        if len <= S::MAX_INLINE_LEN {
+            return Self::new_inline_parts(parts, len);
+        }
+        if len <= S::MAX_PACKED_LEN {
+            let mut buf = [0; packed::MAX_LEN];
+            let mut offset = 0;
+            for part in parts {
+                buf[offset..offset + part.len()].copy_from_slice(part.as_bytes());
+                offset += part.len();
+            }
+            // SAFETY: `buf` holds whole `str`s
+            let s = unsafe { core::str::from_utf8_unchecked(&buf[..len]) };
+            if let Some(atom) = Self::new_small(s) {
+                return atom;
+            }
+        }
+        atom_from_parts(parts, len, parts_hash(parts))
+    }
+
+    pub(crate) fn new_inline_parts(parts: &[&str], len: usize) -> Self {
+        debug_assert!(len <= S::MAX_INLINE_LEN);
+        #[allow(clippy::cast_possible_truncation)]
+        let mut value = TaggedValue::new_inline(len as u8);
+        let bytes = unsafe { value.as_bytes_mut() };
+        let mut offset = 0;
+        for part in parts {
+            bytes[offset..offset + part.len()].copy_from_slice(part.as_bytes());
+            offset += part.len();
+        }
+
+        Self {
+            inner: value,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<'a, S: AtomSize> Atom<'a, S> {
+    /// Get an atom for a substring of this atom.
+    ///
+    /// # Panics
+    ///
+    /// If `range` is out of bounds or does not fall on a char boundary, just
+    /// like slicing a [`str`].
+    #[must_use]
+    pub fn substr<R: RangeBounds<usize>>(&self, range: R) -> Self {
+        let range: (Bound<usize>, Bound<usize>) =
+            (range.start_bound().cloned(), range.end_bound().cloned());
+        let text = self.to_str();
+        let s = &text[range];
+        if s.len() == self.len() {
+            return self.clone();
+        }
+
+        Atom::intern(s)
+    }
+
+    /// Replace all matches of `from` with `to`, like [`str::replace`].
+    ///
+    /// Returns a clone of this atom if `from` does not occur in it.
+    #[must_use]
+    pub fn replace(&self, from: &str, to: &str) -> Self {
+        let text = self.to_str();
+        let s: &str = &text;
+        let mut parts = Vec::new();
+        let mut last_end = 0;
+        for (start, matched) in s.match_indices(from) {
+            parts.push(&s[last_end..start]);
+            parts.push(to);
+            last_end = start + matched.len();
+        }
+        if parts.is_empty() {
+            return self.clone();
+        }
+        parts.push(&s[last_end..]);
+
+        Atom::from_parts(&parts)
+    }
+}
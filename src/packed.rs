@@ -0,0 +1,173 @@
+//! Identifiers packed 6 bits per character, used by [`Packed64`] atoms.
+
+use core::{fmt, ops::Deref};
+
+use crate::size::{AtomSize, Packed64};
+use crate::tags::Tag;
+
+/// Bits per packed character.
+pub(crate) const BITS: usize = 6;
+
+/// Longest string a packed atom's length field can describe.
+pub(crate) const MAX_LEN: usize = (u8::MAX >> Tag::INLINE_LEN_OFFSET) as usize;
+static_assertions::const_assert!(Packed64::MAX_PACKED_LEN <= MAX_LEN);
+static_assertions::const_assert!(Packed64::MAX_PACKED_LEN * BITS <= u64::BITS as usize);
+
+/// Characters that can be packed, in ASCII order. A character's code is its
+/// index.
+const ALPHABET: &[u8; 1 << BITS] =
+    b"$0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ_abcdefghijklmnopqrstuvwxyz";
+
+const INVALID: u8 = u8::MAX;
+
+/// Code of every byte, or [`INVALID`] if it isn't in [`ALPHABET`].
+static CODES: [u8; 256] = {
+    let mut codes = [INVALID; 256];
+    let mut code = 0;
+    while code < ALPHABET.len() {
+        #[allow(clippy::cast_possible_truncation)]
+        {
+            codes[ALPHABET[code] as usize] = code as u8;
+        }
+        code += 1;
+    }
+    codes
+};
+
+/// Pack `s` into the low bits of a `u64`, first character lowest. Returns
+/// `None` if `s` has a character outside of `[A-Za-z0-9_$]`.
+#[inline]
+pub(crate) fn pack(s: &str) -> Option<u64> {
+    debug_assert!(s.len() * BITS <= u64::BITS as usize);
+    let mut bits = 0;
+    for (i, &byte) in s.as_bytes().iter().enumerate() {
+        let code = CODES[usize::from(byte)];
+        if code == INVALID {
+            return None;
+        }
+        bits |= u64::from(code) << (i * BITS);
+    }
+    Some(bits)
+}
+
+/// Decode `out.len()` characters packed by [`pack`] into `out`.
+#[inline]
+pub(crate) fn unpack(bits: u64, out: &mut [u8]) {
+    const MASK: u64 = (1 << BITS) - 1;
+    for (i, byte) in out.iter_mut().enumerate() {
+        #[allow(clippy::cast_possible_truncation)]
+        {
+            *byte = ALPHABET[((bits >> (i * BITS)) & MASK) as usize];
+        }
+    }
+}
+
+/// The text of an [`Atom`](crate::Atom), returned by
+/// [`Atom::to_str`](crate::Atom::to_str).
+///
+/// Borrows the atom's text when it is stored as UTF-8, and holds a decoded
+/// copy for packed atoms. Either way, it derefs to a [`str`].
+#[derive(Clone, Copy)]
+pub struct AtomStr<'a> {
+    repr: Repr<'a>,
+}
+
+#[derive(Clone, Copy)]
+enum Repr<'a> {
+    Borrowed(&'a str),
+    Unpacked { buf: [u8; MAX_LEN], len: u8 },
+}
+
+impl<'a> AtomStr<'a> {
+    #[inline]
+    pub(crate) fn borrowed(s: &'a str) -> Self {
+        Self {
+            repr: Repr::Borrowed(s),
+        }
+    }
+
+    /// Decode `len` characters packed into `bits`.
+    #[inline]
+    pub(crate) fn unpacked(bits: u64, len: usize) -> Self {
+        debug_assert!(len <= MAX_LEN);
+        let mut buf = [0; MAX_LEN];
+        unpack(bits, &mut buf[..len]);
+        #[allow(clippy::cast_possible_truncation)]
+        Self {
+            repr: Repr::Unpacked {
+                buf,
+                len: len as u8,
+            },
+        }
+    }
+
+    #[inline]
+    pub fn as_str(&self) -> &str {
+        match &self.repr {
+            Repr::Borrowed(s) => s,
+            // SAFETY: `ALPHABET` is all ASCII
+            Repr::Unpacked { buf, len } => unsafe {
+                core::str::from_utf8_unchecked(&buf[..usize::from(*len)])
+            },
+        }
+    }
+}
+
+impl Deref for AtomStr<'_> {
+    type Target = str;
+
+    #[inline]
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for AtomStr<'_> {
+    #[inline]
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl fmt::Debug for AtomStr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for AtomStr<'_> {
+    #[inline]
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.as_str(), f)
+    }
+}
+
+impl PartialEq<str> for AtomStr<'_> {
+    #[inline]
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&'_ str> for AtomStr<'_> {
+    #[inline]
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip() {
+        for s in ["", "a", "$", "_z9", "getElemen", "ZZZZZZZZZ"] {
+            let bits = pack(s).unwrap();
+            assert_eq!(AtomStr::unpacked(bits, s.len()), s);
+        }
+        assert_eq!(pack("a-b"), None);
+        assert_eq!(pack("héllo"), None);
+        assert_eq!(pack("a b"), None);
+    }
+}
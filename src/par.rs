@@ -0,0 +1,102 @@
+//! Interning many strings at once with [`rayon`]. Needs the `rayon` feature.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use rayon::prelude::*;
+
+use crate::shared::{lock, SharedAtomStore};
+use crate::store::{hash_if_long, AtomStore};
+use crate::tags::MAX_INLINE_LEN;
+use crate::Atom;
+
+/// Hash `strings` in parallel with [`hash_if_long`], keeping their order.
+fn hash_all<'a>(strings: impl ParallelIterator<Item = &'a str>) -> Vec<(&'a str, u64)> {
+    strings.map(hash_if_long).collect()
+}
+
+impl<V: Default> AtomStore<'_, V> {
+    /// Intern every string in `strings`, returning their atoms in the same
+    /// order.
+    ///
+    /// Strings are hashed in parallel, and then added to the store like
+    /// [`AtomStore::intern_batch`] adds them.
+    ///
+    /// # Panics
+    /// If a string would take the store over one of its limits. See
+    /// [`AtomStore::try_atom`].
+    ///
+    /// ```
+    /// use rayon::prelude::*;
+    /// use stern::AtomStore;
+    ///
+    /// let names = ["if", "an_identifier_from_the_index", "if"];
+    /// let mut store = AtomStore::default();
+    /// let atoms = store.intern_par_iter(names.par_iter().copied());
+    /// assert_eq!(atoms, names);
+    /// ```
+    pub fn intern_par_iter<'a>(
+        &mut self,
+        strings: impl ParallelIterator<Item = &'a str>,
+    ) -> Vec<Atom<'static>> {
+        self.intern_hashed_batch(&hash_all(strings))
+    }
+}
+
+impl SharedAtomStore {
+    /// Intern every string in `strings`, returning their atoms in the same
+    /// order.
+    ///
+    /// Strings are hashed in parallel and grouped by shard. Each shard then
+    /// adds its strings in parallel with the others, taking its lock once,
+    /// like [`AtomStore::intern_batch`] adds them.
+    ///
+    /// ```
+    /// use rayon::prelude::*;
+    /// use stern::SharedAtomStore;
+    ///
+    /// let names: Vec<String> = (0..1000)
+    ///     .map(|i| format!("a_symbol_from_the_index_{i}"))
+    ///     .collect();
+    /// let store = SharedAtomStore::new();
+    /// let atoms = store.intern_par_iter(names.par_iter().map(String::as_str));
+    /// assert!(atoms.iter().zip(&names).all(|(atom, name)| atom == name.as_str()));
+    /// assert_eq!(store.len(), 1000);
+    /// ```
+    pub fn intern_par_iter<'a>(
+        &self,
+        strings: impl ParallelIterator<Item = &'a str>,
+    ) -> Vec<Atom<'static>> {
+        let hashed = hash_all(strings);
+
+        let mut by_shard = vec![Vec::new(); self.shards.len()];
+        for (index, &(s, hash)) in hashed.iter().enumerate() {
+            if s.len() > MAX_INLINE_LEN {
+                by_shard[self.shard_index(hash)].push(index);
+            }
+        }
+        let interned: Vec<Vec<(usize, Atom<'static>)>> = by_shard
+            .into_par_iter()
+            .zip(self.shards.par_iter())
+            .map(|(indexes, shard)| {
+                if indexes.is_empty() {
+                    return Vec::new();
+                }
+                let strings: Vec<(&str, u64)> =
+                    indexes.iter().map(|&index| hashed[index]).collect();
+                let atoms = lock(shard).intern_hashed_batch(&strings);
+                indexes.into_iter().zip(atoms).collect()
+            })
+            .collect();
+
+        let mut atoms: Vec<Option<Atom<'static>>> = hashed
+            .iter()
+            .map(|&(s, _)| (s.len() <= MAX_INLINE_LEN).then(|| Atom::new_inline_impl(s)))
+            .collect();
+        for (index, atom) in interned.into_iter().flatten() {
+            atoms[index] = Some(atom);
+        }
+        // every string was either inlined or interned in its shard
+        atoms.into_iter().map(Option::unwrap).collect()
+    }
+}
@@ -0,0 +1,256 @@
+//! A store that many threads can intern into at once.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use crate::hash::str_hash;
+use crate::heap::HeapAtom;
+use crate::size::AtomSize;
+use crate::store::AtomStore;
+use crate::tags::MAX_INLINE_LEN;
+use crate::Atom;
+
+/// An [`AtomStore`] that can be shared between threads.
+///
+/// Strings are split between shards by their hash, and each shard has its own
+/// lock, so threads interning different strings rarely wait for each other.
+/// Every string still has exactly one entry.
+///
+/// Threads that intern a lot of strings over and over, like tokenizers, can
+/// skip the locks for most of them with an [`AtomCache`].
+///
+/// ```
+/// use stern::SharedAtomStore;
+///
+/// let store = SharedAtomStore::new();
+/// let (a, b) = std::thread::scope(|scope| {
+///     let a = scope.spawn(|| store.atom("a_name_both_threads_use"));
+///     let b = scope.spawn(|| store.atom("a_name_both_threads_use"));
+///     (a.join().unwrap(), b.join().unwrap())
+/// });
+/// assert_eq!(a.as_str().as_ptr(), b.as_str().as_ptr());
+/// ```
+#[derive(Debug)]
+pub struct SharedAtomStore {
+    pub(crate) shards: Box<[Mutex<AtomStore<'static>>]>,
+}
+
+impl Default for SharedAtomStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SharedAtomStore {
+    /// Shards a store has, unless picked with
+    /// [`SharedAtomStore::with_shards`].
+    pub const DEFAULT_SHARDS: usize = 16;
+
+    pub fn new() -> Self {
+        Self::with_shards(Self::DEFAULT_SHARDS)
+    }
+
+    /// Create a store with at least `shards` shards. More shards mean less
+    /// waiting when many threads intern at once.
+    pub fn with_shards(shards: usize) -> Self {
+        Self::from_first_shard(AtomStore::new(), shards)
+    }
+
+    /// Create a store with at least `shards` shards, the first of which is
+    /// `first`.
+    pub(crate) fn from_first_shard(first: AtomStore<'static>, shards: usize) -> Self {
+        let shards = shards.max(1).next_power_of_two();
+        // Every string has its entry in a single shard, so to its atoms the
+        // shards are one store: they share one ID, and one arena.
+        let rest: Vec<_> = (1..shards).map(|_| first.sibling()).collect();
+        Self {
+            shards: core::iter::once(first)
+                .chain(rest)
+                .map(Mutex::new)
+                .collect(),
+        }
+    }
+
+    /// Intern `s`.
+    pub fn atom<S: AsRef<str>>(&self, s: S) -> Atom<'static> {
+        let s = s.as_ref();
+        if s.len() <= MAX_INLINE_LEN {
+            return Atom::new_inline_impl(s);
+        }
+        self.add_atom(s, str_hash(s))
+    }
+
+    /// Like [`SharedAtomStore::atom`], for atoms of any size.
+    pub fn intern<S: AtomSize, T: AsRef<str>>(&self, s: T) -> Atom<'static, S> {
+        let s = s.as_ref();
+        Atom::new_small(s).unwrap_or_else(|| self.add_atom(s, str_hash(s)))
+    }
+
+    /// The atom for `s`, if it is in this store. Strings short enough to be
+    /// inlined don't need an entry, and are always found.
+    pub fn get<S: AsRef<str>>(&self, s: S) -> Option<Atom<'static>> {
+        let s = s.as_ref();
+        if s.len() <= MAX_INLINE_LEN {
+            return Some(Atom::new_inline_impl(s));
+        }
+        self.shard(str_hash(s)).get(s)
+    }
+
+    /// The number of entries in this store.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| lock(shard).len()).sum()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|shard| lock(shard).is_empty())
+    }
+
+    /// Make a cache for one thread to intern through. See [`AtomCache`].
+    pub fn cache(&self, slots: usize) -> AtomCache<'_> {
+        AtomCache::new(self, slots)
+    }
+
+    /// Intern `s`, whose [`str_hash`] is `hash`, in its shard.
+    pub(crate) fn add_atom<S: AtomSize>(&self, s: &str, hash: u64) -> Atom<'static, S> {
+        self.shard(hash).add_atom_parts(&[s], s.len(), hash)
+    }
+
+    /// Index of the shard for strings with this hash.
+    #[inline]
+    #[allow(clippy::cast_possible_truncation)]
+    pub(crate) fn shard_index(&self, hash: u64) -> usize {
+        // The low bits pick the bucket within a shard's table, and the high
+        // bits are the table's tags, so use the ones in between.
+        (hash >> 32) as usize & (self.shards.len() - 1)
+    }
+
+    pub(crate) fn shard(&self, hash: u64) -> MutexGuard<'_, AtomStore<'static>> {
+        lock(&self.shards[self.shard_index(hash)])
+    }
+}
+
+impl Drop for SharedAtomStore {
+    fn drop(&mut self) {
+        // The shards can only give their ID back once none of them has atoms
+        // using it, so take it from them and check them all at once.
+        let mut shards: Vec<&mut AtomStore<'static>> = self
+            .shards
+            .iter_mut()
+            .map(|shard| shard.get_mut().unwrap_or_else(PoisonError::into_inner))
+            .collect();
+        let ids = shards[0].ids;
+        let Some(id) = shards[0].id else { return };
+        for shard in &mut shards {
+            shard.id = None;
+        }
+        ids.release_unused(id, shards.iter().flat_map(|shard| shard.data.keys()));
+    }
+}
+
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// A small cache of recently interned atoms, in front of a
+/// [`SharedAtomStore`].
+///
+/// Each thread makes its own with [`SharedAtomStore::cache`]. A string found
+/// in the cache gets the store's atom for it without taking any lock. The
+/// cache is direct-mapped: every string has one slot, picked by its hash, and
+/// a miss replaces whatever was in it.
+///
+/// ```
+/// use stern::SharedAtomStore;
+///
+/// let store = SharedAtomStore::new();
+/// let mut cache = store.cache(256);
+/// let name = cache.atom("an_identifier_used_over_and_over");
+/// assert_eq!(cache.atom("an_identifier_used_over_and_over"), name);
+/// assert_eq!(cache.stats().hits, 1);
+/// assert_eq!(cache.stats().misses, 1);
+/// ```
+#[derive(Debug)]
+pub struct AtomCache<'s> {
+    store: &'s SharedAtomStore,
+    pub(crate) slots: Box<[Option<Atom<'static>>]>,
+    stats: CacheStats,
+}
+
+/// How often an [`AtomCache`] found what it was asked for. Short strings
+/// never need the store, so they count as neither.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    /// Strings found in the cache.
+    pub hits: u64,
+    /// Strings looked up in the store.
+    pub misses: u64,
+}
+
+impl CacheStats {
+    /// The share of lookups that were hits, from 0 to 1.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / lookups as f64
+    }
+}
+
+impl<'s> AtomCache<'s> {
+    /// A cache with at least `slots` slots.
+    pub fn new(store: &'s SharedAtomStore, slots: usize) -> Self {
+        let slots = slots.max(1).next_power_of_two();
+        Self {
+            store,
+            slots: (0..slots).map(|_| None).collect(),
+            stats: CacheStats::default(),
+        }
+    }
+
+    /// Intern `s` in the store, unless it is in the cache already.
+    pub fn atom<S: AsRef<str>>(&mut self, s: S) -> Atom<'static> {
+        let s = s.as_ref();
+        if s.len() <= MAX_INLINE_LEN {
+            return Atom::new_inline_impl(s);
+        }
+        let hash = str_hash(s);
+        #[allow(clippy::cast_possible_truncation)]
+        let slot = &mut self.slots[hash as usize & (self.slots.len() - 1)];
+        if let Some(atom) = slot {
+            // SAFETY: only heap atoms are cached
+            let heap = unsafe { HeapAtom::deref_from(atom.inner) };
+            if heap.hash() == hash && heap.as_str() == s {
+                self.stats.hits += 1;
+                return atom.clone();
+            }
+        }
+
+        self.stats.misses += 1;
+        let atom: Atom<'static> = self.store.add_atom(s, hash);
+        *slot = Some(atom.clone());
+        atom
+    }
+
+    /// Hits and misses since the cache was made.
+    #[must_use]
+    pub fn stats(&self) -> CacheStats {
+        self.stats
+    }
+
+    /// Empty the cache, so that its atoms can be freed. The stats are kept.
+    pub fn flush(&mut self) {
+        self.slots.fill(None);
+    }
+
+    /// The store this cache is in front of.
+    #[must_use]
+    pub fn store(&self) -> &'s SharedAtomStore {
+        self.store
+    }
+}
@@ -0,0 +1,275 @@
+//! How many bytes an [`Atom`](crate::Atom) takes up.
+//!
+//! [`Atom`](crate::Atom)'s second type parameter picks its representation.
+//! Bigger atoms can hold longer strings inline, while smaller ones pack more
+//! tightly into other data structures. Atoms of every size share the same
+//! stores and heap allocations, so converting between sizes with
+//! [`Atom::resize`](crate::Atom::resize) never copies a heap-allocated string.
+//!
+//! [`Packed64`] atoms additionally pack identifiers 6 bits per character, so
+//! they can't always lend out a `&str`. Use [`Atom::to_str`](crate::Atom::to_str)
+//! with them.
+//!
+//! The `atom_size_*` cargo features only choose [`DefaultSize`]. If several are
+//! enabled, the largest one wins.
+
+#![allow(clippy::cast_possible_truncation)]
+
+use core::fmt;
+use core::mem::{size_of, transmute};
+use core::num::{NonZeroU32, NonZeroU8};
+use core::ptr::NonNull;
+
+use crate::arena;
+use crate::heap::HeapAtom;
+use crate::tags::Tag;
+
+/// A representation for [`Atom`](crate::Atom)s. Implemented by [`Bits128`],
+/// [`Bits64`], [`Bits32`] and [`Packed64`].
+pub trait AtomSize: sealed::Sealed {
+    /// Longest string, in bytes, that gets stored inline instead of on the
+    /// heap.
+    const MAX_INLINE_LEN: usize = size_of::<Self::Repr>() - 1;
+
+    /// Longest identifier that gets packed inline, or 0 if this size doesn't
+    /// pack identifiers. See [`Packed64`].
+    const MAX_PACKED_LEN: usize = 0;
+}
+
+/// Sizes whose atoms always store their text as UTF-8, so they can lend it
+/// out with [`Atom::as_str`](crate::Atom::as_str) and [`Deref`](core::ops::Deref).
+pub trait Utf8Size: AtomSize {}
+
+/// 16-byte atoms, which hold up to 15 bytes inline.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Bits128;
+
+/// 8-byte atoms, which hold up to 7 bytes inline. Heap atoms are pointers.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Bits64;
+
+/// 4-byte atoms, which hold up to 3 bytes inline.
+///
+/// Pointers don't fit in 4 bytes, so every heap atom that is used as an
+/// [`Atom32`](crate::Atom32) gets an index in an arena owned by the store
+/// that made it. Looking up a heap atom's string costs an extra load.
+///
+/// There are 63 arenas of up to 16 million atoms each. Stores made while all
+/// of them are taken share one more arena, which atoms also move to once
+/// their own is full. Only giving an atom its index takes the arena's lock.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Bits32;
+
+/// 8-byte atoms, which hold up to 7 bytes inline, or identifiers of up to 9
+/// characters.
+///
+/// Identifiers made of `[A-Za-z0-9_$]` that are too long to store as UTF-8
+/// get packed 6 bits per character instead. Packed atoms have to be decoded
+/// before they can be read, so `Atom<'_, Packed64>` doesn't implement
+/// [`Deref`](core::ops::Deref). Use [`Atom::to_str`](crate::Atom::to_str)
+/// instead.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Packed64;
+
+impl AtomSize for Bits128 {}
+impl AtomSize for Bits64 {}
+impl AtomSize for Bits32 {}
+impl AtomSize for Packed64 {
+    // The length shares the tag byte, so every other bit holds characters.
+    const MAX_PACKED_LEN: usize = Self::MAX_INLINE_LEN * 8 / crate::packed::BITS;
+}
+
+impl Utf8Size for Bits128 {}
+impl Utf8Size for Bits64 {}
+impl Utf8Size for Bits32 {}
+
+#[cfg(feature = "atom_size_128")]
+pub type DefaultSize = Bits128;
+#[cfg(all(feature = "atom_size_64", not(feature = "atom_size_128")))]
+pub type DefaultSize = Bits64;
+#[cfg(all(
+    feature = "atom_size_32",
+    not(any(feature = "atom_size_128", feature = "atom_size_64"))
+))]
+pub type DefaultSize = Bits32;
+#[cfg(not(any(
+    feature = "atom_size_128",
+    feature = "atom_size_64",
+    feature = "atom_size_32"
+)))]
+pub type DefaultSize = Bits64;
+
+pub(crate) mod sealed {
+    use super::{fmt, transmute, NonNull, NonZeroU8, Tag};
+
+    /// The raw operations a [`TaggedValue`](crate::tags::TaggedValue) needs
+    /// from its representation. All integer operations act on the value, so
+    /// the tag is always in the lowest bits regardless of endianness.
+    pub trait Sealed: Copy + Eq + Default + fmt::Debug + Send + Sync + 'static {
+        /// Storage for a tagged value. Never zero, so `Option<Atom>` is the
+        /// same size as `Atom`.
+        type Repr: Copy + Eq + fmt::Debug;
+
+        /// An empty inline atom.
+        const EMPTY: Self::Repr;
+
+        /// A value whose lowest byte is `tag_byte`, and every other byte is 0.
+        fn new_inline(tag_byte: NonZeroU8) -> Self::Repr;
+
+        fn low_byte(repr: Self::Repr) -> u8;
+
+        /// Fold the whole value into a hash.
+        fn fold(repr: Self::Repr) -> u64;
+
+        /// # Safety
+        /// `ptr` must point to a live [`HeapAtom`](crate::heap::HeapAtom).
+        unsafe fn from_heap(ptr: NonNull<u8>) -> Self::Repr;
+
+        /// # Safety
+        /// `repr` must have been made by [`Sealed::from_heap`], and its atom
+        /// must still be alive.
+        unsafe fn to_heap(repr: Self::Repr) -> *const u8;
+    }
+
+    /// Pointer-sized atoms, plus `HIGH` extra words for inline data.
+    ///
+    /// The pointer always holds the lowest-order bytes, where the tag lives.
+    /// Heap atoms keep a real pointer here rather than an integer, so they
+    /// don't lose its provenance.
+    #[cfg(target_endian = "little")]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[repr(C)]
+    pub struct PtrRepr<const HIGH: usize> {
+        pub(super) low: NonNull<()>,
+        pub(super) high: [usize; HIGH],
+    }
+
+    #[cfg(target_endian = "big")]
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[repr(C)]
+    pub struct PtrRepr<const HIGH: usize> {
+        pub(super) high: [usize; HIGH],
+        pub(super) low: NonNull<()>,
+    }
+
+    impl<const HIGH: usize> PtrRepr<HIGH> {
+        // SAFETY: `Tag::Inline` is non-zero. Inline data isn't a real pointer,
+        // so it's fine for it to have no provenance.
+        #[allow(clippy::useless_transmute)]
+        pub(super) const EMPTY: Self = Self {
+            low: unsafe { transmute::<usize, NonNull<()>>(Tag::Inline as usize) },
+            high: [0; HIGH],
+        };
+
+        #[inline(always)]
+        pub(super) fn new_inline(tag_byte: NonZeroU8) -> Self {
+            Self {
+                // SAFETY: see `EMPTY`
+                #[allow(clippy::useless_transmute)]
+                low: unsafe { transmute::<usize, NonNull<()>>(usize::from(tag_byte.get())) },
+                high: [0; HIGH],
+            }
+        }
+
+        #[inline(always)]
+        pub(super) fn low_byte(self) -> u8 {
+            self.low.as_ptr() as usize as u8
+        }
+
+        #[inline(always)]
+        pub(super) fn fold(self) -> u64 {
+            // Fold in the other words so that inline atoms differing only in
+            // their last bytes don't all collide.
+            self.high
+                .iter()
+                .fold(self.low.as_ptr() as usize as u64, |hash, &word| {
+                    hash ^ word as u64
+                })
+        }
+    }
+}
+
+macro_rules! impl_ptr_repr {
+    ($size:ty, $bytes:expr) => {
+        impl sealed::Sealed for $size {
+            type Repr = sealed::PtrRepr<{ $bytes / size_of::<usize>() - 1 }>;
+
+            const EMPTY: Self::Repr = Self::Repr::EMPTY;
+
+            #[inline(always)]
+            fn new_inline(tag_byte: NonZeroU8) -> Self::Repr {
+                Self::Repr::new_inline(tag_byte)
+            }
+
+            #[inline(always)]
+            fn low_byte(repr: Self::Repr) -> u8 {
+                repr.low_byte()
+            }
+
+            #[inline(always)]
+            fn fold(repr: Self::Repr) -> u64 {
+                repr.fold()
+            }
+
+            #[inline(always)]
+            unsafe fn from_heap(ptr: NonNull<u8>) -> Self::Repr {
+                Self::Repr {
+                    low: ptr.cast(),
+                    high: [0; { $bytes / size_of::<usize>() - 1 }],
+                }
+            }
+
+            #[inline(always)]
+            unsafe fn to_heap(repr: Self::Repr) -> *const u8 {
+                repr.low.as_ptr().cast_const().cast()
+            }
+        }
+    };
+}
+
+impl_ptr_repr!(Bits128, 16);
+impl_ptr_repr!(Bits64, 8);
+impl_ptr_repr!(Packed64, 8);
+
+/// Heap atoms hold the number of an `arena` and an index into it instead of
+/// a pointer:
+///
+/// ```text
+/// aaaa aaii | iiii iiii | iiii iiii | iiii ii00
+/// ```
+impl sealed::Sealed for Bits32 {
+    type Repr = NonZeroU32;
+
+    // SAFETY: `Tag::Inline` is non-zero
+    const EMPTY: Self::Repr = unsafe { NonZeroU32::new_unchecked(Tag::Inline as u32) };
+
+    #[inline(always)]
+    fn new_inline(tag_byte: NonZeroU8) -> Self::Repr {
+        tag_byte.into()
+    }
+
+    #[inline(always)]
+    fn low_byte(repr: Self::Repr) -> u8 {
+        repr.get() as u8
+    }
+
+    #[inline(always)]
+    fn fold(repr: Self::Repr) -> u64 {
+        u64::from(repr.get())
+    }
+
+    #[inline(always)]
+    unsafe fn from_heap(ptr: NonNull<u8>) -> Self::Repr {
+        let slot = HeapAtom::arena_slot(ptr);
+        debug_assert!(slot.get() < 1 << arena::INDEX_BITS);
+        // SAFETY: `slot` is non-zero and fits in 30 bits, so shifting it keeps
+        // it non-zero.
+        NonZeroU32::new_unchecked(slot.get() << Tag::TAG_MASK.count_ones())
+    }
+
+    #[inline(always)]
+    unsafe fn to_heap(repr: Self::Repr) -> *const u8 {
+        let slot = NonZeroU32::new_unchecked(repr.get() >> Tag::TAG_MASK.count_ones());
+        arena::get(slot).cast_const()
+    }
+}
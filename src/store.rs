@@ -1,15 +1,29 @@
 extern crate alloc;
 
+use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 use core::cell::RefCell;
+use core::fmt::Write;
 use core::hash::{BuildHasherDefault, Hasher};
 use core::marker::PhantomData;
+use core::mem::ManuallyDrop;
 use core::num::NonZeroU32;
-use core::ptr::NonNull;
-use core::sync::atomic::{AtomicU32, Ordering};
+use core::ops::Range;
+use core::ptr;
+use core::sync::atomic::{AtomicU64, Ordering};
 
-use crate::heap::{str_hash, HeapAtom};
-use crate::tags::{Tag, TaggedValue, MAX_INLINE_LEN};
+use hashbrown::hash_map::RawEntryMut;
+
+use crate::arena::Claim;
+use crate::config::{AtomStoreBuilder, Limits, StoreError};
+use crate::frozen::FrozenAtomStore;
+use crate::hash::str_hash;
+use crate::heap::{HeapAtom, HeapRc};
+use crate::id::StoreIds;
+use crate::size::AtomSize;
+use crate::tags::MAX_INLINE_LEN;
+use crate::visit::{AtomVisitor, VisitAtoms};
 use crate::Atom;
 
 /// Creates a new [`Atom`] using this thread's global [`AtomStore`].
@@ -19,82 +33,1069 @@ use crate::Atom;
 ///
 /// This method always creates [`HeapAtom`]s. It assumes SSO checks have already
 /// been performed, which is why this is not a public API.
-pub(crate) fn atom(text: &str) -> Atom<'static> {
+pub(crate) fn atom<S: AtomSize>(text: &str) -> Atom<'static, S> {
+    with_global(|store| store.add_atom(text))
+}
+
+/// Like [`atom`], but for a string split into `parts`. `len` and `hash` are
+/// the length and [`str_hash`] of the concatenated string.
+pub(crate) fn atom_from_parts<S: AtomSize>(
+    parts: &[&str],
+    len: usize,
+    hash: u64,
+) -> Atom<'static, S> {
+    with_global(|store| store.add_atom_parts(parts, len, hash))
+}
+
+fn with_global<R>(f: impl FnOnce(&mut AtomStore<'static>) -> R) -> R {
     thread_local! {
-        static GLOBAL_DATA: RefCell<AtomStore> = RefCell::default();
+        static GLOBAL_DATA: RefCell<AtomStore<'static>> = RefCell::default();
     }
 
     GLOBAL_DATA.with(|global| {
         let mut store = global.borrow_mut();
 
-        store.add_atom(text)
+        f(&mut store)
     })
 }
 
-pub struct AtomStore {
+/// Interns strings, deduplicating the heap allocations of long ones.
+///
+/// Every entry can also carry a value of type `V`, which is looked up
+/// straight from an [`Atom`] without hashing its string again. See
+/// [`AtomStore::intern_with`].
+///
+/// A store can be layered on top of a parent store with
+/// [`AtomStore::with_parent`] or [`AtomStore::with_frozen_parent`]. Strings in
+/// the parent are never added again to the child.
+///
+/// Use [`AtomStore::builder`] to pick the store's capacity, or to limit how
+/// big it can get.
+#[derive(Debug)]
+pub struct AtomStore<'p, V = ()> {
     pub(crate) id: Option<NonZeroU32>,
-    pub(crate) data: hashbrown::HashMap<Arc<HeapAtom>, (), BuildAtomHasher>,
+    pub(crate) data: hashbrown::HashMap<HeapRc, V, BuildAtomHasher>,
+    /// Entries of `data`, in the order they were added.
+    log: Vec<Inserted>,
+    /// Sequence number of the next entry added to `log`.
+    next_seq: u64,
+    /// Sequence numbers of checkpoints undone by a rollback, sorted and
+    /// disjoint.
+    undone: Vec<Range<u64>>,
+    /// Tells this store apart from every other one. Unlike `id`, it is never
+    /// reused, and anonymous stores have one too.
+    serial: u64,
+    /// Total length of the strings in `data`.
+    bytes: usize,
+    pub(crate) limits: Limits,
+    /// Where `id` came from, and goes back to.
+    pub(crate) ids: &'static StoreIds,
+    /// Where this store's heap atoms get a slot when used as
+    /// [`Atom32`](crate::Atom32)s. Shared by the shards of a
+    /// [`SharedAtomStore`](crate::SharedAtomStore).
+    pub(crate) arena: Arc<Claim>,
+    parent: Option<Parent<'p, V>>,
+}
+
+static NEXT_SERIAL: AtomicU64 = AtomicU64::new(0);
+
+/// Where to find an entry of [`AtomStore::data`] again.
+#[derive(Debug, Clone, Copy)]
+struct Inserted {
+    hash: u64,
+    addr: usize,
+    /// Counts up with every entry added, and is never reused, so that
+    /// removing entries doesn't move [`Checkpoint`]s.
+    seq: u64,
+}
+
+impl Inserted {
+    #[inline]
+    fn is(self, key: &HeapRc) -> bool {
+        HeapRc::addr(key) == self.addr
+    }
+}
+
+/// A point in an [`AtomStore`]'s history to go back to. See
+/// [`AtomStore::checkpoint`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Checkpoint {
+    store: u64,
+    seq: u64,
 }
 
-impl Default for AtomStore {
+#[derive(Debug)]
+enum Parent<'p, V> {
+    Store(&'p AtomStore<'p, V>),
+    Frozen(&'p FrozenAtomStore<V>),
+}
+
+impl<V> Clone for Parent<'_, V> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<V> Copy for Parent<'_, V> {}
+
+impl<'p, V> Parent<'p, V> {
+    fn find_with(
+        self,
+        hash: u64,
+        eq: impl Fn(&HeapRc) -> bool + Copy,
+    ) -> Option<(&'p HeapRc, &'p V)> {
+        match self {
+            Self::Store(store) => store.find_with(hash, eq),
+            Self::Frozen(store) => store.find_with(hash, eq),
+        }
+    }
+}
+
+impl Default for AtomStore<'_> {
     fn default() -> Self {
-        static ATOM_STORE_ID: AtomicU32 = AtomicU32::new(1);
-        const STORE_CAPACITY: usize = 256;
+        Self::new()
+    }
+}
+
+impl AtomStore<'_> {
+    /// Settings for making a store, with or without payloads. See
+    /// [`AtomStoreBuilder`].
+    pub fn builder() -> AtomStoreBuilder {
+        AtomStoreBuilder::new()
+    }
+}
+
+impl<'p, V> AtomStore<'p, V> {
+    /// Create an empty store. Use [`AtomStore::default`] for stores without
+    /// payloads.
+    pub fn new() -> Self {
+        AtomStoreBuilder::new().build()
+    }
+
+    pub(crate) fn from_builder(builder: AtomStoreBuilder, id: Option<NonZeroU32>) -> Self {
+        Self::with_arena(builder, id, Arc::new(Claim::new()))
+    }
 
+    fn with_arena(builder: AtomStoreBuilder, id: Option<NonZeroU32>, arena: Arc<Claim>) -> Self {
         Self {
-            id: Some(unsafe {
-                NonZeroU32::new_unchecked(ATOM_STORE_ID.fetch_add(1, Ordering::SeqCst))
-            }),
+            id,
             data: hashbrown::HashMap::with_capacity_and_hasher(
-                STORE_CAPACITY,
+                builder.capacity,
                 BuildAtomHasher::default(),
             ),
+            log: Vec::new(),
+            next_seq: 0,
+            undone: Vec::new(),
+            serial: NEXT_SERIAL.fetch_add(1, Ordering::Relaxed),
+            bytes: 0,
+            limits: builder.limits,
+            ids: builder.ids,
+            arena,
+            parent: None,
+        }
+    }
+
+    /// Create an empty store with the same ID and arena as this one, for
+    /// another shard of a [`SharedAtomStore`](crate::SharedAtomStore). Every
+    /// string must only get an entry in one of them, and only one of them may
+    /// give the ID back.
+    pub(crate) fn sibling(&self) -> Self {
+        Self::with_arena(AtomStoreBuilder::new(), self.id, Arc::clone(&self.arena))
+    }
+
+    /// Create an empty store on top of `parent`.
+    ///
+    /// Looking up or interning a string finds `parent`'s entry for it first,
+    /// and only adds it to the new store if `parent` doesn't have it. Atoms
+    /// for the same string are then the same pointer, whichever layer they
+    /// come from.
+    ///
+    /// ```
+    /// use stern::AtomStore;
+    ///
+    /// let mut builtins = AtomStore::default();
+    /// let builtin = builtins.atom("addEventListener");
+    ///
+    /// let mut file = AtomStore::with_parent(&builtins);
+    /// assert_eq!(file.atom("addEventListener"), builtin);
+    /// assert_eq!(file.get("addEventListener"), Some(builtin));
+    /// assert_eq!(builtins.get("a_local_variable"), None);
+    /// ```
+    pub fn with_parent(parent: &'p AtomStore<'p, V>) -> Self {
+        let mut store = Self::new();
+        store.parent = Some(Parent::Store(parent));
+        store
+    }
+
+    /// Like [`AtomStore::with_parent`], on top of a [`FrozenAtomStore`].
+    pub fn with_frozen_parent(parent: &'p FrozenAtomStore<V>) -> Self {
+        let mut store = Self::new();
+        store.parent = Some(Parent::Frozen(parent));
+        store
+    }
+
+    /// Stop changing this store, so that it can be shared between threads.
+    ///
+    /// The frozen store only holds this store's own entries, not those of its
+    /// parents.
+    pub fn freeze(mut self) -> FrozenAtomStore<V> {
+        FrozenAtomStore::new(core::mem::take(&mut self.data), self.id.take(), self.ids)
+    }
+
+    /// Remember which entries this store has, so that the ones added after
+    /// this point can be removed with [`AtomStore::rollback`].
+    #[must_use]
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            store: self.serial,
+            seq: self.next_seq,
+        }
+    }
+
+    /// Remove the entries added since `checkpoint`, and their payloads.
+    ///
+    /// Entries that atoms still point to are kept, and returned. Rolling back
+    /// to a checkpoint undoes the checkpoints taken after it, which can't be
+    /// rolled back to anymore. The checkpoint itself can be rolled back to
+    /// again.
+    ///
+    /// ```
+    /// use stern::AtomStore;
+    ///
+    /// let mut store = AtomStore::default();
+    /// let checkpoint = store.checkpoint();
+    /// store.atom("a_speculative_name");
+    /// let kept = store.atom("a_name_that_is_still_used");
+    ///
+    /// assert_eq!(store.rollback(checkpoint), [kept.clone()]);
+    /// assert_eq!(store.get("a_speculative_name"), None);
+    /// assert_eq!(store.get("a_name_that_is_still_used"), Some(kept));
+    /// ```
+    ///
+    /// # Panics
+    /// If `checkpoint` was taken on another store, or was undone by rolling
+    /// back to an earlier checkpoint.
+    pub fn rollback(&mut self, checkpoint: Checkpoint) -> Vec<Atom<'static>> {
+        assert_eq!(
+            checkpoint.store, self.serial,
+            "checkpoint was taken on another store"
+        );
+        let i = self
+            .undone
+            .partition_point(|undone| undone.end <= checkpoint.seq);
+        assert!(
+            !self
+                .undone
+                .get(i)
+                .is_some_and(|undone| undone.contains(&checkpoint.seq)),
+            "checkpoint was undone by an earlier rollback"
+        );
+        if checkpoint.seq < self.next_seq {
+            // Checkpoints taken since this one are undone. Skip a sequence
+            // number, so that the ones taken from now on aren't.
+            self.next_seq += 1;
+            let mut undone = checkpoint.seq + 1..self.next_seq;
+            while let Some(last) = self.undone.last() {
+                if last.end < undone.start {
+                    break;
+                }
+                undone.start = undone.start.min(last.start);
+                self.undone.pop();
+            }
+            self.undone.push(undone);
+        }
+        let start = self
+            .log
+            .partition_point(|inserted| inserted.seq < checkpoint.seq);
+        let mut kept = Vec::new();
+        let mut len = start;
+        for i in start..self.log.len() {
+            let inserted = self.log[i];
+            let RawEntryMut::Occupied(entry) = self
+                .data
+                .raw_entry_mut()
+                .from_hash(inserted.hash, |key| inserted.is(key))
+            else {
+                continue;
+            };
+            if HeapRc::is_unique(entry.key()) {
+                self.bytes -= entry.key().len();
+                entry.remove();
+            } else {
+                kept.push(Atom {
+                    inner: entry.key().clone().into_tagged(),
+                    marker: PhantomData,
+                });
+                self.log[len] = inserted;
+                len += 1;
+            }
+        }
+        self.log.truncate(len);
+        kept
+    }
+
+    /// Move every entry of `other` into this store.
+    ///
+    /// Strings this store or its parents already have keep their entry and
+    /// payload here, and `other`'s atoms for them keep working. They just
+    /// aren't the same pointer as this store's atoms; use
+    /// [`AtomStore::canonicalize`] to swap them for this store's.
+    ///
+    /// # Panics
+    /// If this would take the store over one of its limits. Entries moved
+    /// before that stay in this store.
+    pub fn merge(&mut self, mut other: AtomStore<'_, V>) {
+        // `other`'s atoms outlive it in this store, so its ID is retired.
+        other.id = None;
+        self.data.reserve(other.data.len());
+        self.log.reserve(other.log.len());
+        // Keep `other`'s insertion order, so that rolling back past the
+        // merge removes its entries too.
+        for inserted in core::mem::take(&mut other.log) {
+            let RawEntryMut::Occupied(entry) = other
+                .data
+                .raw_entry_mut()
+                .from_hash(inserted.hash, |key| inserted.is(key))
+            else {
+                continue;
+            };
+            let (key, value) = entry.remove_entry();
+            self.adopt_entry(key, || value)
+                .unwrap_or_else(limit_exceeded);
+        }
+    }
+
+    /// The atom for `s`, if it is in this store or one of its parents.
+    /// Strings short enough to be inlined don't need an entry, and are always
+    /// found.
+    pub fn get<S: AsRef<str>>(&self, s: S) -> Option<Atom<'static>> {
+        let s = s.as_ref();
+        if s.len() <= MAX_INLINE_LEN {
+            return Some(Atom::new_inline_impl(s));
+        }
+        let (entry, _) = self.find(&[s], s.len(), str_hash(s))?;
+        Some(Atom {
+            inner: entry.clone().into_tagged(),
+            marker: PhantomData,
+        })
+    }
+
+    /// Intern `s`, and give its entry a payload made by `value` if it doesn't
+    /// have one yet.
+    ///
+    /// Unlike the other ways of interning, this adds an entry for short
+    /// strings too, even though their atoms are stored inline.
+    ///
+    /// `value` is only called when the entry is new. Entries added by
+    /// [`AtomStore::atom`], [`AtomStore::intern_batch`] or [`Extend`] already
+    /// have a payload, `V::default()`, and so do the ones
+    /// [`AtomStore::merge`] takes over from another store. Use
+    /// [`AtomStore::set_payload`] to replace it.
+    ///
+    /// ```
+    /// use stern::AtomStore;
+    ///
+    /// #[derive(Debug, PartialEq)]
+    /// enum Kind {
+    ///     Keyword,
+    ///     Identifier,
+    /// }
+    ///
+    /// let mut store = AtomStore::new();
+    /// let r#if = store.intern_with("if", || Kind::Keyword);
+    /// let name = store.intern_with("a_long_variable_name", || Kind::Identifier);
+    ///
+    /// assert_eq!(store.payload(&r#if), Some(&Kind::Keyword));
+    /// assert_eq!(store.payload(&name), Some(&Kind::Identifier));
+    /// ```
+    ///
+    /// # Panics
+    /// If adding the entry would take the store over one of its limits. See
+    /// [`AtomStore::try_intern_with`].
+    pub fn intern_with<S: AsRef<str>>(&mut self, s: S, value: impl FnOnce() -> V) -> Atom<'static> {
+        self.try_intern_with(s, value)
+            .unwrap_or_else(limit_exceeded)
+    }
+
+    /// Like [`AtomStore::intern_with`], but returns an error instead of going
+    /// over the store's limits.
+    ///
+    /// # Errors
+    /// If adding the entry would take the store over one of its limits.
+    pub fn try_intern_with<S: AsRef<str>>(
+        &mut self,
+        s: S,
+        value: impl FnOnce() -> V,
+    ) -> Result<Atom<'static>, StoreError> {
+        let s = s.as_ref();
+        let entry = self.insert_entry(&[s], s.len(), str_hash(s), value)?;
+        if s.len() <= MAX_INLINE_LEN {
+            return Ok(Atom::new_inline_impl(s));
+        }
+        Ok(Atom {
+            inner: entry.clone().into_tagged(),
+            marker: PhantomData,
+        })
+    }
+
+    /// Intern `s`, and set the payload of its entry to `value`, replacing the
+    /// one it had if it already had an entry.
+    ///
+    /// ```
+    /// use stern::AtomStore;
+    ///
+    /// let mut store: AtomStore<bool> = AtomStore::new();
+    /// let name = store.atom("a_long_variable_name");
+    /// assert_eq!(store.payload(&name), Some(&false));
+    ///
+    /// store.set_payload("a_long_variable_name", true);
+    /// assert_eq!(store.payload(&name), Some(&true));
+    /// ```
+    ///
+    /// # Panics
+    /// If adding the entry would take the store over one of its limits, or
+    /// if the entry belongs to a parent. See [`AtomStore::try_set_payload`].
+    pub fn set_payload<S: AsRef<str>>(&mut self, s: S, value: V) -> Atom<'static> {
+        self.try_set_payload(s, value)
+            .unwrap_or_else(limit_exceeded)
+    }
+
+    /// Like [`AtomStore::set_payload`], but returns an error instead of
+    /// panicking.
+    ///
+    /// # Errors
+    /// If adding the entry would take the store over one of its limits, or
+    /// with [`StoreError::InParent`] if one of the store's parents has the
+    /// entry, since parents can't be changed.
+    pub fn try_set_payload<S: AsRef<str>>(
+        &mut self,
+        s: S,
+        value: V,
+    ) -> Result<Atom<'static>, StoreError> {
+        let s = s.as_ref();
+        let hash = str_hash(s);
+        let eq = |key: &HeapRc| key.hash() == hash && key.as_str() == s;
+        let entry = match self.data.raw_entry_mut().from_hash(hash, eq) {
+            RawEntryMut::Occupied(mut entry) => {
+                *entry.get_mut() = value;
+                entry.into_key()
+            }
+            RawEntryMut::Vacant(_) => {
+                if self
+                    .parent
+                    .is_some_and(|parent| parent.find_with(hash, eq).is_some())
+                {
+                    return Err(StoreError::InParent);
+                }
+                self.insert_entry(&[s], s.len(), hash, || value)?
+            }
+        };
+        if s.len() <= MAX_INLINE_LEN {
+            return Ok(Atom::new_inline_impl(s));
+        }
+        Ok(Atom {
+            inner: entry.clone().into_tagged(),
+            marker: PhantomData,
+        })
+    }
+
+    /// The payload of `atom`'s entry in this store or one of its parents, if
+    /// it has one.
+    pub fn payload<S: AtomSize>(&self, atom: &Atom<'_, S>) -> Option<&V> {
+        let text = atom.to_str();
+        let (hash, heap) = if atom.is_heap() {
+            let heap = unsafe { HeapAtom::deref_from(atom.inner) };
+            (heap.hash(), Some(heap))
+        } else {
+            (str_hash(&text), None)
+        };
+        self.find_with(hash, |key| entry_matches(key, hash, &text, heap))
+            .map(|(_, value)| value)
+    }
+
+    /// Like [`AtomStore::payload`], but lets the payload be changed. Parents
+    /// can't be changed, so this only finds entries in this store.
+    pub fn payload_mut<S: AtomSize>(&mut self, atom: &Atom<'_, S>) -> Option<&mut V> {
+        let text = atom.to_str();
+        let (hash, heap) = if atom.is_heap() {
+            let heap = unsafe { HeapAtom::deref_from(atom.inner) };
+            (heap.hash(), Some(heap))
+        } else {
+            (str_hash(&text), None)
+        };
+        match self
+            .data
+            .raw_entry_mut()
+            .from_hash(hash, |key| entry_matches(key, hash, &text, heap))
+        {
+            RawEntryMut::Occupied(entry) => Some(entry.into_mut()),
+            RawEntryMut::Vacant(_) => None,
+        }
+    }
+
+    /// The number of entries in this store, not counting its parents.
+    ///
+    /// Short strings only get an entry from [`AtomStore::intern_with`].
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// The atoms for this store's entries, in the order they were added.
+    /// Entries of its parents are left out.
+    pub fn iter(&self) -> impl Iterator<Item = Atom<'static>> + '_ {
+        self.entries().map(|(key, _)| {
+            if key.len() <= MAX_INLINE_LEN {
+                return Atom::new_inline_impl(key.as_str());
+            }
+            Atom {
+                inner: key.clone().into_tagged(),
+                marker: PhantomData,
+            }
+        })
+    }
+
+    /// Every string in this store, sorted, one per line. Gives the same
+    /// output whatever order the strings were added in, for golden-file
+    /// tests.
+    ///
+    /// ```
+    /// use stern::AtomStore;
+    ///
+    /// let mut store = AtomStore::default();
+    /// store.atom("the_second_function_name");
+    /// store.atom("the_first_function_name");
+    /// store.intern_with("if", || ());
+    /// assert_eq!(
+    ///     store.dump(),
+    ///     "\"if\"\n\"the_first_function_name\"\n\"the_second_function_name\"\n",
+    /// );
+    /// ```
+    #[must_use]
+    pub fn dump(&self) -> String {
+        let mut strings: Vec<&str> = self.data.keys().map(|key| key.as_str()).collect();
+        strings.sort_unstable();
+        let mut out = String::new();
+        for s in strings {
+            // writing to a `String` can't fail
+            let _ = writeln!(out, "{s:?}");
+        }
+        out
+    }
+
+    /// Remove the entries whose strings `keep` returns `false` for, and their
+    /// payloads.
+    ///
+    /// Like [`AtomStore::rollback`], entries that atoms still point to are
+    /// kept. Otherwise, interning the string again would make a second
+    /// allocation for it, and atoms for it would no longer be equal.
+    /// Checkpoints taken before can still be rolled back to.
+    pub fn retain(&mut self, mut keep: impl FnMut(&str) -> bool) {
+        let len = self.data.len();
+        let bytes = &mut self.bytes;
+        self.data.retain(|key, _| {
+            let retained = keep(key.as_str()) || !HeapRc::is_unique(key);
+            if !retained {
+                *bytes -= key.len();
+            }
+            retained
+        });
+        if self.data.len() == len {
+            return;
+        }
+        let data = &self.data;
+        self.log.retain(|inserted| {
+            data.raw_entry()
+                .from_hash(inserted.hash, |key| inserted.is(key))
+                .is_some()
+        });
+    }
+
+    /// Remove every entry that no atom points to. See [`AtomStore::retain`].
+    pub fn clear(&mut self) {
+        self.retain(|_| false);
+    }
+
+    /// Free unused capacity.
+    pub fn shrink_to_fit(&mut self) {
+        self.data.shrink_to_fit();
+        self.log.shrink_to_fit();
+    }
+
+    /// Make room for at least `additional` more entries.
+    pub fn reserve(&mut self, additional: usize) {
+        self.data.reserve(additional);
+        self.log.reserve(additional);
+    }
+
+    /// This store's own entries, in the order they were added.
+    fn entries(&self) -> impl Iterator<Item = (&HeapRc, &V)> + '_ {
+        self.log.iter().filter_map(|inserted| {
+            self.data
+                .raw_entry()
+                .from_hash(inserted.hash, |key| inserted.is(key))
+        })
+    }
+
+    /// The entry for the concatenation of `parts` in this store or one of its
+    /// parents.
+    fn find(&self, parts: &[&str], len: usize, hash: u64) -> Option<(&HeapRc, &V)> {
+        self.find_with(hash, |key| {
+            key.hash() == hash && key.len() == len && parts_eq(key.as_str(), parts)
+        })
+    }
+
+    fn find_with(&self, hash: u64, eq: impl Fn(&HeapRc) -> bool + Copy) -> Option<(&HeapRc, &V)> {
+        self.data
+            .raw_entry()
+            .from_hash(hash, eq)
+            .or_else(|| self.parent?.find_with(hash, eq))
+    }
+
+    /// The entry for the same string as `key`, adding `key` itself to this
+    /// store if neither it nor its parents have one already.
+    fn adopt_entry(
+        &mut self,
+        key: HeapRc,
+        value: impl FnOnce() -> V,
+    ) -> Result<&HeapRc, StoreError> {
+        let hash = key.hash();
+        let in_parent = self
+            .parent
+            .and_then(|parent| parent.find_with(hash, |entry| **entry == *key));
+        if let Some((entry, _)) = in_parent {
+            return Ok(entry);
+        }
+
+        match self
+            .data
+            .raw_entry_mut()
+            .from_hash(hash, |entry| *entry == key)
+        {
+            RawEntryMut::Occupied(entry) => Ok(entry.into_key()),
+            RawEntryMut::Vacant(entry) => {
+                self.limits
+                    .check_entry(self.log.len(), self.bytes, key.len())?;
+                self.bytes += key.len();
+                self.log.push(Inserted {
+                    hash,
+                    addr: HeapRc::addr(&key),
+                    seq: self.next_seq,
+                });
+                self.next_seq += 1;
+                let (entry, _) = entry.insert(key, value());
+                Ok(entry)
+            }
+        }
+    }
+
+    /// The entry for the concatenation of `parts`, adding one to this store
+    /// if neither it nor its parents have one already.
+    #[inline(never)]
+    fn insert_entry(
+        &mut self,
+        parts: &[&str],
+        len: usize,
+        hash: u64,
+        value: impl FnOnce() -> V,
+    ) -> Result<&HeapRc, StoreError> {
+        self.insert_hashed(parts, len, hash, value)
+    }
+
+    /// [`AtomStore::insert_entry`], inlined into loops that add many strings.
+    #[inline]
+    fn insert_hashed(
+        &mut self,
+        parts: &[&str],
+        len: usize,
+        hash: u64,
+        value: impl FnOnce() -> V,
+    ) -> Result<&HeapRc, StoreError> {
+        let in_parent = self.parent.and_then(|parent| {
+            parent.find_with(hash, |key| {
+                key.hash() == hash && key.len() == len && parts_eq(key.as_str(), parts)
+            })
+        });
+        if let Some((entry, _)) = in_parent {
+            return Ok(entry);
+        }
+
+        let store_id = self.id;
+        let arena = self.arena.tag();
+        match self.data.raw_entry_mut().from_hash(hash, |key| {
+            key.hash() == hash && key.len() == len && parts_eq(key.as_str(), parts)
+        }) {
+            RawEntryMut::Occupied(entry) => Ok(entry.into_key()),
+            RawEntryMut::Vacant(entry) => {
+                self.limits.check_entry(self.log.len(), self.bytes, len)?;
+                let key = HeapAtom::from_parts(parts, len, hash, store_id, arena);
+                self.bytes += len;
+                self.log.push(Inserted {
+                    hash,
+                    addr: HeapRc::addr(&key),
+                    seq: self.next_seq,
+                });
+                self.next_seq += 1;
+                let (entry, _) = entry.insert(key, value());
+                Ok(entry)
+            }
         }
     }
 }
 
-impl AtomStore {
+impl<V: Default> AtomStore<'_, V> {
+    /// Intern `s`. Strings too long to inline get an entry with a default
+    /// payload.
+    ///
+    /// # Panics
+    /// If `s` would take the store over one of its limits. See
+    /// [`AtomStore::try_atom`].
     pub fn atom<S: AsRef<str>>(&mut self, s: S) -> Atom<'static> {
         let s = s.as_ref();
         if s.len() <= MAX_INLINE_LEN {
+            self.limits
+                .check_len(s.len())
+                .unwrap_or_else(limit_exceeded);
             return Atom::new_inline_impl(s);
         }
         self.add_atom(s)
     }
 
-    pub(crate) fn add_atom(&mut self, s: &str) -> Atom<'static> {
-        let hash = str_hash(s);
-        let entry = self.insert_entry(s, hash);
-        let entry = Arc::into_raw(entry);
+    /// Like [`AtomStore::atom`], but returns an error instead of going over
+    /// the store's limits. See [`AtomStoreBuilder`].
+    ///
+    /// # Errors
+    /// If `s` is longer than the store accepts, or adding an entry for it
+    /// would take the store over one of its limits.
+    pub fn try_atom<S: AsRef<str>>(&mut self, s: S) -> Result<Atom<'static>, StoreError> {
+        self.try_intern(s)
+    }
 
-        // Safety: Arc::into_raw returns a non-null pointer
-        let ptr: NonNull<HeapAtom> = unsafe { NonNull::new_unchecked(entry.cast_mut()) };
-        debug_assert!(0 == (ptr.as_ptr() as *const u8 as usize) & Tag::MASK_USIZE);
+    /// Like [`AtomStore::atom`], but with the string's hash already computed
+    /// by an [`AtomHasher`](crate::AtomHasher).
+    ///
+    /// The hash is checked against `s`, since a wrong one would give `s` a
+    /// second entry whose atoms compare unequal to the store's other atoms
+    /// for `s`. Hashing `s` again costs much less than looking it up.
+    ///
+    /// ```
+    /// use stern::{AtomHasher, AtomStore};
+    ///
+    /// let mut store = AtomStore::default();
+    /// let s = "a_rather_long_identifier";
+    /// let atom = store.atom_prehashed(s, AtomHasher::hash_str(s));
+    /// assert_eq!(atom, store.atom(s));
+    /// ```
+    ///
+    /// # Panics
+    /// If `hash` is not the hash of `s`, or `s` would take the store over one
+    /// of its limits. See [`AtomStore::try_atom_prehashed`].
+    pub fn atom_prehashed(&mut self, s: &str, hash: u64) -> Atom<'static> {
+        self.try_atom_prehashed(s, hash)
+            .unwrap_or_else(limit_exceeded)
+    }
+
+    /// Like [`AtomStore::atom_prehashed`], but returns an error instead of
+    /// panicking.
+    ///
+    /// ```
+    /// use stern::{AtomHasher, AtomStore, StoreError};
+    ///
+    /// let mut store = AtomStore::default();
+    /// let s = "a_rather_long_identifier";
+    /// assert!(store.try_atom_prehashed(s, AtomHasher::hash_str(s)).is_ok());
+    /// assert_eq!(store.try_atom_prehashed(s, 0), Err(StoreError::WrongHash));
+    /// ```
+    ///
+    /// # Errors
+    /// [`StoreError::WrongHash`] if `hash` is not the hash of `s`, or like
+    /// [`AtomStore::try_atom`].
+    pub fn try_atom_prehashed(&mut self, s: &str, hash: u64) -> Result<Atom<'static>, StoreError> {
+        if hash != str_hash(s) {
+            return Err(StoreError::WrongHash);
+        }
+        self.limits.check_len(s.len())?;
+        if s.len() <= MAX_INLINE_LEN {
+            return Ok(Atom::new_inline_impl(s));
+        }
+        let entry = self.insert_entry(&[s], s.len(), hash, V::default)?;
+        Ok(Atom {
+            inner: entry.clone().into_tagged(),
+            marker: PhantomData,
+        })
+    }
+
+    /// Like [`AtomStore::atom`], for atoms of any size. Atoms of different
+    /// sizes made by the same store share their heap allocations.
+    pub fn intern<S: AtomSize, T: AsRef<str>>(&mut self, s: T) -> Atom<'static, S> {
+        self.try_intern(s).unwrap_or_else(limit_exceeded)
+    }
+
+    /// Like [`AtomStore::intern`], but returns an error instead of going over
+    /// the store's limits.
+    ///
+    /// # Errors
+    /// Like [`AtomStore::try_atom`].
+    pub fn try_intern<S: AtomSize, T: AsRef<str>>(
+        &mut self,
+        s: T,
+    ) -> Result<Atom<'static, S>, StoreError> {
+        let s = s.as_ref();
+        self.limits.check_len(s.len())?;
+        if let Some(atom) = Atom::new_small(s) {
+            return Ok(atom);
+        }
+        let entry = self.insert_entry(&[s], s.len(), str_hash(s), V::default)?;
+        Ok(Atom {
+            inner: entry.clone().into_tagged(),
+            marker: PhantomData,
+        })
+    }
+
+    /// Like [`AtomStore::atom`], but the atom is never freed. See
+    /// [`Atom::leak`].
+    pub fn intern_immortal<S: AsRef<str>>(&mut self, s: S) -> Atom<'static> {
+        self.atom(s).leak()
+    }
+
+    /// This store's atom for the same string as `atom`, which may come from
+    /// another store.
+    ///
+    /// If this store doesn't have the string yet, it takes `atom`'s heap
+    /// allocation as its entry, without copying the string.
+    ///
+    /// ```
+    /// use stern::AtomStore;
+    ///
+    /// let mut file = AtomStore::default();
+    /// let mut program = AtomStore::default();
+    /// let name = program.atom("a_name_used_by_every_file");
+    ///
+    /// let foreign = file.atom("a_name_used_by_every_file");
+    /// let canonical = program.canonicalize(&foreign);
+    /// assert_eq!(canonical, name);
+    /// assert_eq!(canonical.as_str().as_ptr(), name.as_str().as_ptr());
+    /// ```
+    ///
+    /// # Panics
+    /// If taking `atom`'s allocation would take the store over one of its
+    /// limits.
+    pub fn canonicalize<S: AtomSize>(&mut self, atom: &Atom<'_, S>) -> Atom<'static, S> {
+        if !atom.is_heap() {
+            return Atom {
+                inner: atom.inner,
+                marker: PhantomData,
+            };
+        }
+        let heap = unsafe { HeapAtom::deref_from(atom.inner) };
+        let hash = heap.hash();
+        let found = self.find_with(hash, |key| ptr::eq(&**key, heap) || **key == *heap);
+        let entry = if let Some((entry, _)) = found {
+            entry
+        } else {
+            let atom = ManuallyDrop::new(atom.clone());
+            // SAFETY: the clone's reference is handed over to the store.
+            let key = unsafe { HeapAtom::restore_rc(atom.inner) };
+            self.adopt_entry(key, V::default)
+                .unwrap_or_else(limit_exceeded)
+        };
         Atom {
-            inner: TaggedValue::new_ptr(ptr),
+            inner: entry.clone().into_tagged(),
             marker: PhantomData,
         }
     }
 
-    #[inline(never)]
-    fn insert_entry(&mut self, text: &str, hash: u64) -> Arc<HeapAtom> {
-        let store_id = self.id;
-        let (entry, ()) = self
-            .data
-            .raw_entry_mut()
-            .from_hash(hash, |key| key.hash() == hash && key.as_str() == text)
-            .or_insert_with(move || (HeapAtom::new(text, store_id), ()));
+    /// Replace every atom in `value` with this store's atom for the same
+    /// string. See [`AtomStore::canonicalize`].
+    ///
+    /// ```
+    /// use stern::{Atom, AtomStore};
+    ///
+    /// let mut file = AtomStore::default();
+    /// let mut names: Vec<Option<Atom>> = vec![Some(file.atom("a_local_variable_name")), None];
+    ///
+    /// let mut program = AtomStore::default();
+    /// program.canonicalize_all(&mut names);
+    /// drop(file);
+    /// assert_eq!(program.get("a_local_variable_name"), names[0]);
+    /// ```
+    pub fn canonicalize_all<T: VisitAtoms + ?Sized>(&mut self, value: &mut T) {
+        value.visit_atoms(self);
+    }
+
+    /// Intern every string in `strings`, returning their atoms in the same
+    /// order.
+    ///
+    /// The strings are all hashed first, and the store makes room for the
+    /// new ones once, instead of growing as they are added. This is not
+    /// measurably faster than calling [`AtomStore::atom`] on each string.
+    ///
+    /// ```
+    /// use stern::AtomStore;
+    ///
+    /// let names = ["if", "an_identifier_from_the_cache", "if"];
+    /// let mut store = AtomStore::default();
+    /// let atoms = store.intern_batch(&names);
+    /// assert_eq!(atoms, names);
+    /// assert_eq!(store.len(), 1);
+    /// ```
+    ///
+    /// # Panics
+    /// If a string would take the store over one of its limits. The strings
+    /// before it are added by then.
+    pub fn intern_batch(&mut self, strings: &[&str]) -> Vec<Atom<'static>> {
+        let strings: Vec<(&str, u64)> = strings.iter().map(|&s| hash_if_long(s)).collect();
+        self.intern_hashed_batch(&strings)
+    }
+
+    /// [`AtomStore::intern_batch`], for strings already hashed with
+    /// [`hash_if_long`].
+    pub(crate) fn intern_hashed_batch(&mut self, strings: &[(&str, u64)]) -> Vec<Atom<'static>> {
+        let mut atoms = Vec::with_capacity(strings.len());
+        self.insert_batch(strings, |s, entry| {
+            atoms.push(match entry {
+                Some(entry) => Atom {
+                    inner: entry.clone().into_tagged(),
+                    marker: PhantomData,
+                },
+                None => Atom::new_inline_impl(s),
+            });
+        });
+        atoms
+    }
+
+    /// Add every string in `strings`, hashed with [`hash_if_long`], calling
+    /// `each` with its entry, or `None` if it is short enough to be inlined.
+    fn insert_batch(
+        &mut self,
+        strings: &[(&str, u64)],
+        mut each: impl FnMut(&str, Option<&HeapRc>),
+    ) {
+        let long = strings
+            .iter()
+            .filter(|(s, _)| s.len() > MAX_INLINE_LEN)
+            .count();
+        // like `HashMap::extend`, assume about half the strings are already
+        // in a store that isn't empty
+        self.reserve(if self.data.is_empty() {
+            long
+        } else {
+            (long + 1) / 2
+        });
+
+        for &(s, hash) in strings {
+            if s.len() <= MAX_INLINE_LEN {
+                self.limits
+                    .check_len(s.len())
+                    .unwrap_or_else(limit_exceeded);
+                each(s, None);
+                continue;
+            }
+            let entry = self
+                .insert_hashed(&[s], s.len(), hash, V::default)
+                .unwrap_or_else(limit_exceeded);
+            each(s, Some(entry));
+        }
+    }
+
+    pub(crate) fn add_atom<S: AtomSize>(&mut self, s: &str) -> Atom<'static, S> {
+        self.add_atom_parts(&[s], s.len(), str_hash(s))
+    }
+
+    pub(crate) fn add_atom_parts<S: AtomSize>(
+        &mut self,
+        parts: &[&str],
+        len: usize,
+        hash: u64,
+    ) -> Atom<'static, S> {
+        let entry = self
+            .insert_entry(parts, len, hash, V::default)
+            .unwrap_or_else(limit_exceeded);
+        Atom {
+            inner: entry.clone().into_tagged(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<V> Drop for AtomStore<'_, V> {
+    fn drop(&mut self) {
+        if let Some(id) = self.id {
+            self.ids.release_unused(id, self.data.keys());
+        }
+    }
+}
+
+/// Interns every string, like [`AtomStore::intern_batch`] without keeping
+/// the atoms.
+impl<V: Default, T: AsRef<str>> Extend<T> for AtomStore<'_, V> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) {
+        let owned: Vec<T> = iter.into_iter().collect();
+        let strings: Vec<(&str, u64)> = owned.iter().map(|s| hash_if_long(s.as_ref())).collect();
+        self.insert_batch(&strings, |_, _| {});
+    }
+}
 
-        entry.clone()
+impl<V: Default> AtomVisitor for AtomStore<'_, V> {
+    #[inline]
+    fn visit<S: AtomSize>(&mut self, atom: &mut Atom<'_, S>) {
+        *atom = self.canonicalize(atom);
+    }
+}
+
+/// `s` and its hash, or 0 if it is short enough to be inlined and doesn't
+/// need one.
+#[inline]
+pub(crate) fn hash_if_long(s: &str) -> (&str, u64) {
+    let hash = if s.len() <= MAX_INLINE_LEN {
+        0
+    } else {
+        str_hash(s)
+    };
+    (s, hash)
+}
+
+#[cold]
+#[inline(never)]
+#[track_caller]
+pub(crate) fn limit_exceeded<T>(err: StoreError) -> T {
+    panic!("{err}")
+}
+
+/// Check if `key` is the entry for an atom with the given `hash` and `text`.
+/// `heap` is the atom's allocation, if it has one.
+#[inline]
+pub(crate) fn entry_matches(key: &HeapRc, hash: u64, text: &str, heap: Option<&HeapAtom>) -> bool {
+    match heap {
+        Some(heap) => ptr::eq(&**key, heap) || **key == *heap,
+        None => key.hash() == hash && key.as_str() == text,
+    }
+}
+
+/// Check if `s` is the concatenation of `parts`. Assumes their total lengths
+/// have already been checked.
+pub(crate) fn parts_eq(mut s: &str, parts: &[&str]) -> bool {
+    for part in parts {
+        let (head, tail) = s.as_bytes().split_at(part.len());
+        if head != part.as_bytes() {
+            return false;
+        }
+        // SAFETY: `head` matched `part`, so `tail` starts on a char boundary
+        s = unsafe { core::str::from_utf8_unchecked(tail) };
     }
+    true
 }
 
-type BuildAtomHasher = BuildHasherDefault<EntryHasher>;
+pub(crate) type BuildAtomHasher = BuildHasherDefault<EntryHasher>;
 
 /// A "no-op" hasher for [`HeapAtom`] that returns [`HeapAtom::hash`]. The design is
 /// inspired by the `nohash-hasher` crate.
 ///
-/// Assumption: [Arc]'s implementation of [Hash] is a simple pass-through.
+/// Assumption: [`HeapRc`]'s implementation of [Hash] is a simple pass-through.
 #[derive(Default)]
 pub(crate) struct EntryHasher {
     hash: u64,
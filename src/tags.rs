@@ -1,27 +1,9 @@
 #![allow(clippy::cast_possible_truncation)]
 
-use core::{
-    mem::{size_of, transmute},
-    num::NonZeroU8,
-    ptr::NonNull,
-    slice,
-};
-use std::os::raw::c_void;
-
-#[cfg(all(feature = "atom_size_128", feature = "atom_size_64"))]
-compile_error!(
-    "feature \"atom_size_128\" and feature \"atom_size_64\" cannot be enabled at the same time."
-);
-
-#[cfg(all(feature = "atom_size_128", feature = "atom_size_32"))]
-compile_error!(
-    "feature \"atom_size_128\" and feature \"atom_size_32\" cannot be enabled at the same time."
-);
-
-#[cfg(all(feature = "atom_size_64", feature = "atom_size_32"))]
-compile_error!(
-    "feature \"atom_size_64\" and feature \"atom_size_32\" cannot be enabled at the same time."
-);
+use core::{marker::PhantomData, mem::size_of, num::NonZeroU8, ptr::NonNull, slice};
+
+use crate::packed::{self, AtomStr};
+use crate::size::{AtomSize, DefaultSize};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
@@ -41,9 +23,11 @@ impl Tag {
     }
     pub const TAG_MASK: u8 = 0b_11;
     pub const MASK_USIZE: usize = Self::TAG_MASK as usize;
-    pub const MASK_RAW_VALUE: RawTaggedValue = Self::TAG_MASK as RawTaggedValue;
     pub const INLINE_NONZERO: NonZeroU8 = unsafe { NonZeroU8::new_unchecked(Self::Inline as u8) };
     pub const INLINE_LEN_OFFSET: u8 = 4;
+    /// Set in an inline tag byte when the characters are packed. See
+    /// [`crate::packed`].
+    pub const INLINE_PACKED: u8 = 0b_100;
 
     #[inline(always)]
     pub const fn is_heap_owned(self) -> bool {
@@ -88,6 +72,15 @@ cccc cccc | cccc cccc | cccc cccc | cccc cccc | cccc cccc | cccc cccc | cccc ccc
 - l: length
 - t: tag
 
+### Packed identifiers
+Atoms that pack identifiers (`Packed64`) store ones too long for the layout
+above as 6-bit character codes instead. Bit 2 of the tag byte marks them.
+
+kkkk kkkk | kkkk kkkk | kkkk kkkk | kkkk kkkk | kkkk kkkk | kkkk kkkk | 00kk kkkk | llll 0101
+    0           1           2           3           4           5           6           7
+- k: character code
+- l: length
+
 --------------------------------------------------------------------------------
 
 ## Variant type 3: Static
@@ -111,150 +104,119 @@ Tag is 0b11
 pppp pppp | pppp pppp | pppp pppp | pppp pppp | pppp pppp | pppp pppp | pppp pppp | pppp pp11
     0           1           2           3           4           5           6           7
 */
-#[cfg(feature = "atom_size_128")]
-type RawTaggedValue = u128;
-#[cfg(any(
-    target_pointer_width = "32",
-    target_pointer_width = "16",
-    feature = "atom_size_64"
-))]
-type RawTaggedValue = u64;
-#[cfg(not(any(
-    target_pointer_width = "32",
-    target_pointer_width = "16",
-    feature = "atom_size_64",
-    feature = "atom_size_128"
-)))]
-type RawTaggedValue = usize;
-
-#[cfg(feature = "atom_size_128")]
-type RawTaggedNonZeroValue = core::num::NonZeroU128;
-#[cfg(any(
-    target_pointer_width = "32",
-    target_pointer_width = "16",
-    feature = "atom_size_64"
-))]
-type RawTaggedNonZeroValue = core::num::NonZeroU64;
-#[cfg(not(any(
-    target_pointer_width = "32",
-    target_pointer_width = "16",
-    feature = "atom_size_64",
-    feature = "atom_size_128"
-)))]
-type RawTaggedNonZeroValue = NonNull<()>;
-
-pub(crate) const MAX_INLINE_LEN: usize = core::mem::size_of::<TaggedValue>() - 1;
-
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) const MAX_INLINE_LEN: usize = DefaultSize::MAX_INLINE_LEN;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(transparent)]
-pub(crate) struct TaggedValue {
-    value: RawTaggedNonZeroValue,
+pub(crate) struct TaggedValue<S: AtomSize = DefaultSize> {
+    value: S::Repr,
+    marker: PhantomData<S>,
 }
 
-#[cfg(feature = "atom_size_128")]
-static_assertions::assert_eq_align!(TaggedValue, u128);
-#[cfg(not(feature = "atom_size_128"))]
-static_assertions::assert_eq_align!(TaggedValue, u64);
+impl<S: AtomSize> TaggedValue<S> {
+    const INLINE_DATA_LEN: usize = size_of::<Self>() - 1;
 
-impl TaggedValue {
-    const INLINE_DATA_LEN: usize = size_of::<TaggedValue>() - 1;
+    /// An empty inline value.
+    pub const EMPTY: Self = Self {
+        value: S::EMPTY,
+        marker: PhantomData,
+    };
 
+    /// # Safety
+    /// `ptr` must point to a live [`HeapAtom`](crate::heap::HeapAtom), and be
+    /// aligned to [`ALIGNMENT`](crate::ALIGNMENT).
     #[inline(always)]
-    pub fn new_ptr<T: ?Sized>(value: NonNull<T>) -> Self {
-        #[cfg(any(
-            target_pointer_width = "32",
-            target_pointer_width = "16",
-            feature = "atom_size_64",
-            feature = "atom_size_128"
-        ))]
-        unsafe {
-            #[allow(clippy::transmute_int_to_non_zero)]
-            let value: core::num::NonZeroUsize = transmute(value.cast::<()>());
-            Self {
-                value: RawTaggedNonZeroValue::new_unchecked(value.get() as _),
-            }
+    pub unsafe fn new_heap(ptr: NonNull<u8>) -> Self {
+        debug_assert!(0 == (ptr.as_ptr() as usize) & Tag::MASK_USIZE);
+        Self {
+            value: S::from_heap(ptr),
+            marker: PhantomData,
         }
+    }
 
-        #[cfg(not(any(
-            target_pointer_width = "32",
-            target_pointer_width = "16",
-            feature = "atom_size_64",
-            feature = "atom_size_128"
-        )))]
-        {
-            Self {
-                value: value.cast(),
-            }
-        }
+    #[inline(always)]
+    pub fn new_inline(len: u8) -> Self {
+        debug_assert!(len as usize <= S::MAX_INLINE_LEN);
+        Self::new_inline_tagged(len, 0)
+    }
+
+    /// Pack an identifier into an inline value. Returns `None` if `s` has
+    /// characters that can't be packed.
+    pub fn new_packed(s: &str) -> Option<Self> {
+        debug_assert!(s.len() <= S::MAX_PACKED_LEN);
+        let bits = packed::pack(s)?;
+        let mut value = Self::new_inline_tagged(s.len() as u8, Tag::INLINE_PACKED);
+        let bytes = unsafe { value.as_bytes_mut() };
+        let len = bytes.len().min(size_of::<u64>());
+        bytes[..len].copy_from_slice(&bits.to_le_bytes()[..len]);
+        Some(value)
     }
 
-    pub const fn new_inline(len: u8) -> Self {
-        debug_assert!(len <= MAX_INLINE_LEN as u8);
-        // let value = Tag::INLINE_NONZERO | len << (Tag::INLINE_LEN_OFFSET as NonZeroU8)
+    #[inline(always)]
+    fn new_inline_tagged(len: u8, flags: u8) -> Self {
+        // SAFETY: the inline tag is non-zero
         let tag_byte = unsafe {
-            NonZeroU8::new_unchecked(Tag::INLINE_NONZERO.get() | (len << Tag::INLINE_LEN_OFFSET))
+            NonZeroU8::new_unchecked(
+                Tag::INLINE_NONZERO.get() | flags | (len << Tag::INLINE_LEN_OFFSET),
+            )
         };
-        let value = tag_byte.get() as RawTaggedValue;
         Self {
-            #[allow(clippy::transmute_int_to_non_zero)]
-            value: unsafe { transmute(value) },
+            value: S::new_inline(tag_byte),
+            marker: PhantomData,
         }
     }
 
+    /// Whether an inline value holds a packed identifier.
     #[inline(always)]
-    pub const fn get_ptr(self) -> *const c_void {
-        #[cfg(any(
-            target_pointer_width = "32",
-            target_pointer_width = "16",
-            feature = "atom_size_64",
-            feature = "atom_size_128"
-        ))]
-        {
-            self.value.get() as usize as _
-        }
-        #[cfg(not(any(
-            target_pointer_width = "32",
-            target_pointer_width = "16",
-            feature = "atom_size_64",
-            feature = "atom_size_128"
-        )))]
-        unsafe {
-            transmute(Some(self.value))
-        }
+    pub(crate) fn is_packed(self) -> bool {
+        debug_assert!(self.tag().is_inline());
+        S::MAX_PACKED_LEN != 0 && self.tag_byte() & Tag::INLINE_PACKED != 0
+    }
+
+    /// Decode a packed identifier.
+    pub(crate) fn unpack(&self) -> AtomStr<'static> {
+        debug_assert!(self.is_packed());
+        let bytes = self.as_bytes();
+        let mut bits = [0; size_of::<u64>()];
+        let len = bytes.len().min(bits.len());
+        bits[..len].copy_from_slice(&bytes[..len]);
+        AtomStr::unpacked(u64::from_le_bytes(bits), self.len())
     }
 
+    /// Pointer to the [`HeapAtom`](crate::heap::HeapAtom) of a heap-owned
+    /// value.
+    ///
+    /// # Safety
+    /// This value must be heap-owned, and its atom must still be alive.
     #[inline(always)]
-    #[allow(clippy::unnecessary_cast)]
-    pub const fn hash(self) -> u64 {
-        self.get_value() as u64
+    pub unsafe fn get_ptr(self) -> *const u8 {
+        debug_assert!(self.tag().is_heap_owned());
+        S::to_heap(self.value)
     }
 
     #[inline(always)]
-    pub(crate) const fn tag_byte(self) -> u8 {
-        (self.get_value() & 0xff) as u8
+    pub fn hash(self) -> u64 {
+        S::fold(self.value)
     }
 
     #[inline(always)]
-    pub(crate) const fn tag(self) -> Tag {
-        // NOTE: Dony does this, but tag mask is 0x03?
-        // (self.get_value() & 0xff) as u8
-        unsafe { Tag::new_unchecked((self.get_value() & Tag::MASK_RAW_VALUE) as u8) }
+    pub(crate) fn tag_byte(self) -> u8 {
+        S::low_byte(self.value)
     }
 
-    pub(crate) const fn len(self) -> usize {
+    #[inline(always)]
+    pub(crate) fn tag(self) -> Tag {
+        unsafe { Tag::new_unchecked(self.tag_byte() & Tag::TAG_MASK) }
+    }
+
+    pub(crate) fn len(self) -> usize {
         debug_assert!(self.tag().is_inline());
 
         (self.tag_byte() >> Tag::INLINE_LEN_OFFSET) as usize
     }
 
-    #[inline(always)]
-    const fn get_value(self) -> RawTaggedValue {
-        unsafe { transmute(Some(self.value)) }
-    }
-
     /// Get a slice to the data inlined in this [`TaggedValue`]
     pub fn as_bytes(&self) -> &[u8] {
-        // debug_assert_eq!(self.tag(), Tag::Inline);
         debug_assert!(self.tag().is_inline());
 
         let x: *const _ = &self.value;
@@ -285,3 +247,8 @@ impl TaggedValue {
         slice::from_raw_parts_mut(data, Self::INLINE_DATA_LEN)
     }
 }
+
+static_assertions::assert_eq_size!(TaggedValue<crate::Bits128>, u128);
+static_assertions::assert_eq_size!(TaggedValue<crate::Bits64>, u64);
+static_assertions::assert_eq_size!(TaggedValue<crate::Bits32>, u32);
+static_assertions::assert_eq_size!(Option<TaggedValue>, TaggedValue);
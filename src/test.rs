@@ -1,4 +1,7 @@
 use super::*;
+use crate::id::StoreIds;
+
+mod differential;
 
 /// Atom whose length is on max inline boundary
 fn largest_inline() -> Atom<'static> {
@@ -35,7 +38,7 @@ fn test_len() {
     assert_eq!(smallest_heap().len(), MAX_INLINE_LEN + 1);
 }
 
-fn store_with_atoms(texts: Vec<&str>) -> (AtomStore, Vec<Atom>) {
+fn store_with_atoms(texts: Vec<&str>) -> (AtomStore<'static>, Vec<Atom<'static>>) {
     let mut store = AtomStore::default();
 
     let atoms = { texts.into_iter().map(|text| store.atom(text)).collect() };
@@ -90,3 +93,935 @@ fn store_multiple() {
     assert_eq!(a1.get_hash(), a2.get_hash(), "Same string should be equal");
     assert_eq!(a1, a2, "Same string should be equal");
 }
+
+#[test]
+fn to_ascii_lowercase() {
+    let lower = Atom::new("div");
+    assert_eq!(lower.to_ascii_lowercase().inner, lower.inner);
+    assert_eq!(Atom::new("DIV").to_ascii_lowercase(), "div");
+
+    let long = Atom::new("background-color-is-already-lowercase");
+    assert_eq!(long.to_ascii_lowercase().inner, long.inner);
+    assert_eq!(
+        Atom::new("Background-Color-Is-Not-Lowercase").to_ascii_lowercase(),
+        "background-color-is-not-lowercase"
+    );
+}
+
+#[test]
+fn normalized_store() {
+    let mut store = NormalizedAtomStore::new(Normalization::AsciiLowercase);
+
+    let upper = store.atom("SELECT_FROM_WHERE");
+    let lower = store.atom("select_from_where");
+    let mixed = store.atom("Select_From_Where");
+
+    assert_eq!(upper.as_str(), "SELECT_FROM_WHERE");
+    assert_eq!(mixed.as_str(), "Select_From_Where");
+    assert_eq!(upper.normalized().inner, lower.normalized().inner);
+    assert_eq!(lower.original().inner, lower.normalized().inner);
+    assert!(upper.eq_ignore_case(&mixed));
+    assert_eq!(upper, lower);
+    assert!(!upper.eq_ignore_case(&store.atom("SELECT_FROM_WHEN")));
+}
+
+#[test]
+fn normalized_store_custom() {
+    let mut store = NormalizedAtomStore::new(Normalization::Custom(|s| Cow::Borrowed(s.trim())));
+
+    let padded = store.atom("  a_padded_identifier  ");
+    let trimmed = store.atom("a_padded_identifier");
+    assert_eq!(padded.as_str(), "  a_padded_identifier  ");
+    assert_eq!(padded.normalized(), "a_padded_identifier");
+    assert!(padded.eq_ignore_case(&trimmed));
+    assert_eq!(trimmed.original().inner, trimmed.normalized().inner);
+}
+
+#[cfg(feature = "unicode")]
+#[test]
+fn normalized_store_unicode() {
+    let mut folded = NormalizedAtomStore::new(Normalization::CaseFold);
+    assert!(folded
+        .atom("STRASSE")
+        .eq_ignore_case(&folded.atom("straße")));
+
+    let mut nfc = NormalizedAtomStore::new(Normalization::Nfc);
+    assert!(nfc.atom("e\u{301}").eq_ignore_case(&nfc.atom("\u{e9}")));
+}
+
+#[test]
+fn concat() {
+    assert_eq!(Atom::concat(&[]), "");
+    assert_eq!(Atom::concat(&["a", "", "b"]), "ab");
+    assert!(!Atom::concat(&["a", "b"]).is_heap());
+
+    let long = Atom::concat(&["a very long string ", "that is built ", "from parts"]);
+    assert!(long.is_heap());
+    assert_eq!(long, "a very long string that is built from parts");
+    assert_eq!(
+        long.inner,
+        Atom::new("a very long string that is built from parts").inner
+    );
+}
+
+#[test]
+fn substr() {
+    let atom = Atom::new("this string will be sliced up");
+    assert_eq!(atom.substr(..4), "this");
+    assert_eq!(atom.substr(5..11), "string");
+    assert_eq!(atom.substr(5..), "string will be sliced up");
+    assert_eq!(atom.substr(..).inner, atom.inner);
+    assert_eq!(
+        atom.substr(5..).inner,
+        Atom::new("string will be sliced up").inner
+    );
+}
+
+#[test]
+fn replace() {
+    let atom = Atom::new("foo.bar.baz");
+    assert_eq!(atom.replace(".", "$"), "foo$bar$baz");
+    assert_eq!(atom.replace(".", "::"), "foo::bar::baz");
+    assert_eq!(atom.replace("qux", "").inner, atom.inner);
+    assert_eq!(Atom::new("a.b").replace(".", ""), "ab");
+}
+
+#[test]
+fn format() {
+    let a = Atom::new("foo");
+    let b = Atom::new("bar");
+    assert_eq!(atom_format!("{a}${b}"), "foo$bar");
+    assert_eq!(atom_format!("{a}${b}").is_heap(), MAX_INLINE_LEN < 7);
+    assert_eq!(atom_format!("static"), "static");
+
+    let long = atom_format!("{a}_{b}_{}", "a_long_suffix_that_spills");
+    assert!(long.is_heap());
+    assert_eq!(
+        long.inner,
+        Atom::new("foo_bar_a_long_suffix_that_spills").inner
+    );
+}
+
+#[test]
+fn builder() {
+    use core::fmt::Write;
+
+    let mut builder = AtomBuilder::new();
+    assert!(builder.is_empty());
+    builder.push_str("_ZN");
+    builder.push('3');
+    assert_eq!(builder.as_str(), "_ZN3");
+    let short = builder.clone().finish();
+    assert_eq!(short.is_heap(), MAX_INLINE_LEN < 4);
+    assert_eq!(short, "_ZN3");
+
+    write!(builder, "foo{}bar", 3).unwrap();
+    builder.push_str("E_with_a_long_suffix");
+    assert_eq!(builder.len(), "_ZN3foo3barE_with_a_long_suffix".len());
+    let long = builder.clone().finish();
+    assert!(long.is_heap());
+    assert_eq!(
+        long.inner,
+        Atom::new("_ZN3foo3barE_with_a_long_suffix").inner
+    );
+
+    let mut store = AtomStore::default();
+    let in_store = builder.finish_in(&mut store);
+    assert_eq!(
+        in_store.inner,
+        store.atom("_ZN3foo3barE_with_a_long_suffix").inner
+    );
+    assert_eq!(in_store.get_hash(), long.get_hash());
+}
+
+#[test]
+#[should_panic(expected = "longer than the atom store accepts")]
+fn builder_max_len() {
+    let mut store: AtomStore = AtomStore::builder().max_len(2).build();
+    let mut builder = AtomBuilder::new();
+    builder.push_str("abc");
+    builder.finish_in(&mut store);
+}
+
+#[test]
+fn immortal() {
+    let long = "constructor_with_a_name_too_long_to_inline";
+    let mut store = AtomStore::default();
+    let a = store.intern_immortal(long);
+    assert!(a.is_heap());
+    assert_eq!(a.inner, store.atom(long).inner);
+    assert!(unsafe { HeapAtom::deref_from(a.inner) }.is_immortal());
+
+    drop(store);
+    let b = a.clone();
+    drop(a);
+    assert_eq!(b, long);
+
+    let leaked = Atom::new("foo").leak();
+    assert_eq!(leaked, "foo");
+    let mut store = AtomStore::default();
+    let leaked = store.atom(long).leak();
+    assert!(unsafe { HeapAtom::deref_from(leaked.inner) }.is_immortal());
+    drop(store);
+
+    free_immortal(b);
+    free_immortal(leaked);
+}
+
+/// Free an immortal atom, so Miri doesn't report it as a leak. `atom` must be
+/// the only atom left pointing to it.
+fn free_immortal(atom: Atom<'static>) {
+    let heap = unsafe { HeapAtom::deref_from(atom.inner) };
+    heap.header
+        .refcount
+        .store(1, core::sync::atomic::Ordering::Relaxed);
+    drop(atom);
+}
+
+#[test]
+fn sizes() {
+    use core::mem::size_of;
+
+    assert_eq!(size_of::<Atom128>(), 16);
+    assert_eq!(size_of::<Atom64>(), 8);
+    assert_eq!(size_of::<Atom32>(), 4);
+    assert_eq!(size_of::<Option<Atom32>>(), 4);
+    assert_eq!(Bits128::MAX_INLINE_LEN, 15);
+    assert_eq!(Bits32::MAX_INLINE_LEN, 3);
+
+    for s in [
+        "",
+        "foo",
+        "fooo",
+        "0123456789abcde",
+        "a string on the heap for every size",
+    ] {
+        let big = Atom128::intern(s);
+        let word = Atom64::intern(s);
+        let small = Atom32::intern(s);
+        assert_eq!(big, s);
+        assert_eq!(word, s);
+        assert_eq!(small, s);
+        assert_eq!(small.len(), s.len());
+
+        // conversions land on the same representation as interning directly
+        assert_eq!(small.clone().resize::<Bits128>().inner, big.inner);
+        assert_eq!(big.clone().resize::<Bits32>(), small);
+        assert_eq!(word.clone().resize::<Bits32>(), small);
+        assert_eq!(small.resize::<Bits64>().inner, word.inner);
+    }
+
+    // heap atoms share one allocation across sizes
+    let mut store = AtomStore::default();
+    let long = "shared_between_every_size_of_atom";
+    let small: Atom32 = store.intern(long);
+    let big: Atom128 = store.intern(long);
+    assert!(small.is_heap() && big.is_heap());
+    assert!(core::ptr::eq(
+        unsafe { HeapAtom::deref_from(small.inner) },
+        unsafe { HeapAtom::deref_from(big.inner) }
+    ));
+    assert_eq!(small.substr(..6), Atom32::intern("shared"));
+    assert_eq!(big.replace("_", ""), "sharedbetweeneverysizeofatom");
+    assert_eq!(big.resize::<Bits32>(), small);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn deserialize_char() {
+    use serde::de::{value::Error, Deserialize, IntoDeserializer};
+
+    // a 4-byte char doesn't fit inline in a 32-bit atom
+    for c in ['a', 'é', '😀'] {
+        let atom = Atom32::deserialize(IntoDeserializer::<Error>::into_deserializer(c)).unwrap();
+        assert_eq!(atom, c.to_string().as_str());
+        let atom = Atom64::deserialize(IntoDeserializer::<Error>::into_deserializer(c)).unwrap();
+        assert_eq!(atom, c.to_string().as_str());
+    }
+}
+
+#[test]
+fn atom32_outlives_store() {
+    let (a, b) = {
+        let mut first = AtomStore::default();
+        let mut second = AtomStore::default();
+        let a: Atom32 = first.intern("an_identifier_in_the_first_store");
+        let b: Atom32 = second.intern("an_identifier_in_the_second_store");
+        assert_ne!(a.inner, b.inner);
+        (a, b)
+    };
+    // the stores are gone, but their arenas live on while their atoms do
+    assert_eq!(a, "an_identifier_in_the_first_store");
+    assert_eq!(b.clone(), "an_identifier_in_the_second_store");
+    assert_eq!(a.resize::<Bits64>(), "an_identifier_in_the_first_store");
+}
+
+#[test]
+fn packed_identifiers() {
+    assert_eq!(Packed64::MAX_PACKED_LEN, 9);
+
+    for s in ["", "foo", "_private", "$element", "Component", "a$b_c_d_9"] {
+        let atom = AtomPacked64::intern(s);
+        assert!(atom.is_inline(), "{s} should be inline");
+        assert_eq!(atom.to_str(), s);
+        assert_eq!(atom, s);
+        assert_eq!(atom.len(), s.len());
+        assert_eq!(atom.to_string(), s);
+        assert_eq!(atom.clone().resize::<Bits128>(), Atom128::intern(s));
+        assert_eq!(Atom128::intern(s).resize::<Packed64>().inner, atom.inner);
+    }
+
+    // too long, or not an identifier
+    for s in ["Components", "data-attr", "héllo_wo"] {
+        let atom = AtomPacked64::intern(s);
+        assert!(atom.is_heap());
+        assert_eq!(atom, s);
+    }
+
+    let atom = AtomPacked64::intern("PropsType");
+    assert_eq!(atom.to_ascii_lowercase(), "propstype");
+    assert!(atom.to_ascii_lowercase().is_inline());
+    assert_eq!(atom.substr(..5), "Props");
+    assert_eq!(
+        atom.replace("Type", "Kind").inner,
+        AtomPacked64::intern("PropsKind").inner
+    );
+    assert_eq!(
+        Atom::<Packed64>::from_parts(&["Props", "Kind"]).inner,
+        AtomPacked64::intern("PropsKind").inner
+    );
+
+    // the same long identifier is shared across sizes
+    let long: AtomPacked64 = AtomPacked64::intern("a_long_identifier");
+    assert_eq!(
+        long.clone().resize::<Bits64>(),
+        Atom64::intern("a_long_identifier")
+    );
+}
+
+#[test]
+fn prehashed() {
+    let mut store = AtomStore::default();
+    for s in ["", "foo", "a_long_identifier_from_the_lexer"] {
+        let atom = store.atom_prehashed(s, AtomHasher::hash_str(s));
+        assert_eq!(atom.inner, store.atom(s).inner);
+        let checked = store.try_atom_prehashed(s, AtomHasher::hash_str(s));
+        assert_eq!(checked.unwrap().inner, atom.inner);
+    }
+
+    let s = "a_long_identifier_from_the_lexer";
+    assert_eq!(store.try_atom_prehashed(s, 0), Err(StoreError::WrongHash));
+    assert_eq!(store.len(), 1);
+}
+
+#[test]
+#[should_panic = "does not match"]
+fn prehashed_wrong_hash() {
+    let mut store = AtomStore::default();
+    let _ = store.atom_prehashed("a_long_identifier_from_the_lexer", 0);
+}
+
+#[test]
+fn payloads() {
+    let mut store: AtomStore<u32> = AtomStore::new();
+    let long = "an_identifier_long_enough_for_the_heap";
+    let short = store.intern_with("if", || 1);
+    let heap = store.intern_with(long, || 2);
+    assert!(heap.is_heap());
+
+    assert_eq!(store.payload(&short), Some(&1));
+    assert_eq!(store.payload(&heap), Some(&2));
+    // only the first payload is kept
+    assert_eq!(store.intern_with(long, || 3).inner, heap.inner);
+    assert_eq!(store.payload(&heap), Some(&2));
+
+    *store.payload_mut(&heap).unwrap() += 10;
+    assert_eq!(store.payload(&heap), Some(&12));
+
+    // atoms for the same string work too, whoever made them
+    assert_eq!(store.payload(&Atom::new("if")), Some(&1));
+    assert_eq!(store.payload(&Atom::new(long)), Some(&12));
+    assert_eq!(store.payload(&heap.clone().resize::<Bits32>()), Some(&12));
+    assert_eq!(store.payload(&Atom128::intern("if")), Some(&1));
+
+    assert_eq!(store.payload(&Atom::new("else")), None);
+    let other = store.atom("another_identifier_on_the_heap");
+    assert_eq!(store.payload(&other), Some(&0));
+    assert!(store.payload_mut(&Atom::new("while")).is_none());
+
+    // `intern_with` keeps the payload of an existing entry, `set_payload`
+    // replaces it
+    assert_eq!(store.intern_with(&*other, || 5).inner, other.inner);
+    assert_eq!(store.payload(&other), Some(&0));
+    assert_eq!(store.set_payload(&*other, 5).inner, other.inner);
+    assert_eq!(store.payload(&other), Some(&5));
+    assert_eq!(store.set_payload("while", 6), "while");
+    assert_eq!(store.payload(&Atom::new("while")), Some(&6));
+    store.set_payload("if", 7);
+    assert_eq!(store.payload(&short), Some(&7));
+}
+
+#[test]
+fn layered_stores() {
+    let builtin = "addEventListener_from_the_builtins";
+    let local = "a_variable_only_this_file_uses";
+
+    let mut builtins: AtomStore<u8> = AtomStore::new();
+    let b = builtins.intern_with(builtin, || 1);
+
+    let mut file = AtomStore::with_parent(&builtins);
+    assert_eq!(file.atom(builtin).inner, b.inner);
+    assert_eq!(file.get(builtin).unwrap().inner, b.inner);
+    assert!(file.data.is_empty(), "parent entries aren't copied");
+    assert_eq!(file.payload(&b), Some(&1));
+    assert_eq!(file.payload_mut(&b), None);
+    assert_eq!(file.try_set_payload(builtin, 2), Err(StoreError::InParent));
+    assert_eq!(file.payload(&b), Some(&1));
+
+    assert!(file.get(local).is_none());
+    let l = file.atom(local);
+    assert_eq!(file.get(local).unwrap().inner, l.inner);
+    assert!(builtins.get(local).is_none());
+    assert_eq!(file.data.len(), 1);
+
+    // short strings are always found
+    assert_eq!(file.get("if").unwrap(), "if");
+
+    let mut nested = AtomStore::with_parent(&file);
+    assert_eq!(nested.atom(builtin).inner, b.inner);
+    assert_eq!(nested.atom(local).inner, l.inner);
+    drop(nested);
+    drop(file);
+    assert_eq!(l, local);
+}
+
+#[test]
+fn frozen_parent() {
+    fn assert_send_sync<T: Send + Sync>(_: &T) {}
+
+    let builtin = "addEventListener_from_the_builtins";
+    let mut builtins: AtomStore<u8> = AtomStore::new();
+    let b = builtins.intern_with(builtin, || 1);
+    let frozen = builtins.freeze();
+    assert_send_sync(&frozen);
+    assert_eq!(frozen.get(builtin).unwrap().inner, b.inner);
+
+    let check = |frozen: &FrozenAtomStore<u8>| {
+        let mut file = AtomStore::with_frozen_parent(frozen);
+        assert_eq!(file.atom(builtin).inner, b.inner);
+        assert_eq!(file.payload(&b), Some(&1));
+        let local = file.atom("a_variable_only_this_file_uses");
+        assert!(frozen.get(&*local).is_none());
+
+        let mut nested = AtomStore::with_parent(&file);
+        assert_eq!(nested.atom(builtin).inner, b.inner);
+        assert_eq!(nested.atom(&*local).inner, local.inner);
+    };
+    check(&frozen);
+    check(&frozen.with_perfect_hash());
+}
+
+#[test]
+fn checkpoint_rollback() {
+    let before = "interned_before_the_checkpoint";
+    let mut store: AtomStore<u8> = AtomStore::new();
+    let b = store.atom(before);
+
+    let outer = store.checkpoint();
+    store.intern_with("if", || 1);
+    drop(store.atom("abandoned_branch_name"));
+    let kept = store.atom("still_used_after_the_rollback");
+
+    let inner = store.checkpoint();
+    store.atom("abandoned_in_the_inner_branch");
+    assert!(store.rollback(inner).is_empty());
+    assert!(store.get("abandoned_in_the_inner_branch").is_none());
+    assert_eq!(store.data.len(), 4);
+
+    let still_used = store.rollback(outer);
+    assert_eq!(still_used.len(), 1);
+    assert_eq!(still_used[0].inner, kept.inner);
+    assert_eq!(store.data.len(), 2);
+    assert!(store.get("abandoned_branch_name").is_none());
+    assert_eq!(store.payload(&Atom::new("if")), None);
+    assert_eq!(store.get(before).unwrap().inner, b.inner);
+
+    // once the atom is gone, rolling back again removes the entry
+    drop((kept, still_used));
+    assert!(store.rollback(outer).is_empty());
+    assert_eq!(store.data.len(), 1);
+    assert_eq!(store.atom(before).inner, b.inner);
+}
+
+#[test]
+fn rollback_after_retain() {
+    let mut store = AtomStore::default();
+    store.atom("interned_before_the_checkpoint");
+    let checkpoint = store.checkpoint();
+    store.clear();
+    store.atom("the_first_name_after_clearing");
+    store.atom("the_second_name_after_clearing");
+    assert!(store.rollback(checkpoint).is_empty());
+    assert!(store.is_empty());
+
+    let before = store.atom("interned_before_the_checkpoint");
+    let checkpoint = store.checkpoint();
+    store.atom("removed_by_retain");
+    store.atom("added_after_the_checkpoint");
+    store.retain(|s| s != "removed_by_retain");
+    store.atom("added_after_retain");
+    assert!(store.rollback(checkpoint).is_empty());
+    assert_eq!(store.dump(), "\"interned_before_the_checkpoint\"\n");
+    assert_eq!(
+        store.get("interned_before_the_checkpoint").unwrap().inner,
+        before.inner
+    );
+}
+
+#[test]
+fn rollback_undoes_later_checkpoints() {
+    let mut store = AtomStore::default();
+    let outer = store.checkpoint();
+    store.atom("added_after_the_outer_checkpoint");
+    let _inner = store.checkpoint();
+    store.atom("added_after_the_inner_checkpoint");
+    store.rollback(outer);
+
+    // checkpoints taken after the rollback, and the one rolled back to, work
+    let after = store.checkpoint();
+    store.atom("added_after_the_rollback");
+    store.rollback(after);
+    assert!(store.is_empty());
+    store.atom("added_after_the_rollback");
+    store.rollback(outer);
+    assert!(store.is_empty());
+}
+
+#[test]
+#[should_panic = "checkpoint was undone by an earlier rollback"]
+fn rollback_stale_checkpoint() {
+    let mut store = AtomStore::default();
+    let outer = store.checkpoint();
+    store.atom("added_after_the_outer_checkpoint");
+    let inner = store.checkpoint();
+    store.rollback(outer);
+    store.atom("added_after_the_rollback");
+    store.rollback(inner);
+}
+
+#[test]
+#[should_panic = "checkpoint was undone by an earlier rollback"]
+fn rollback_stale_nested_checkpoint() {
+    let mut store = AtomStore::default();
+    let first = store.checkpoint();
+    store.atom("added_after_the_first_checkpoint");
+    let stale = store.checkpoint();
+    store.atom("added_after_the_stale_checkpoint");
+    let second = store.checkpoint();
+    store.atom("added_after_the_second_checkpoint");
+    store.rollback(second);
+    store.rollback(first);
+    store.atom("added_after_both_rollbacks");
+    store.rollback(stale);
+}
+
+#[test]
+#[should_panic = "checkpoint was taken on another store"]
+fn rollback_other_store() {
+    let checkpoint = AtomStore::default().checkpoint();
+    AtomStore::default().rollback(checkpoint);
+}
+
+#[test]
+fn merge_stores() {
+    let shared = "a_name_both_files_use";
+    let only_b = "a_name_only_the_second_file_uses";
+
+    let mut a: AtomStore<u8> = AtomStore::new();
+    let a_shared = a.intern_with(shared, || 1);
+    let mut b: AtomStore<u8> = AtomStore::new();
+    let b_shared = b.intern_with(shared, || 2);
+    let b_only = b.intern_with(only_b, || 3);
+    b.intern_with("if", || 4);
+
+    let checkpoint = a.checkpoint();
+    a.merge(b);
+    assert_eq!(a.data.len(), 3);
+    assert_eq!(a.get(shared).unwrap().inner, a_shared.inner);
+    assert_eq!(a.get(only_b).unwrap().inner, b_only.inner);
+    assert_eq!(a.payload(&b_shared), Some(&1), "existing payloads win");
+    assert_eq!(a.payload(&b_only), Some(&3));
+    assert_eq!(a.payload(&Atom::new("if")), Some(&4));
+    assert_eq!(b_shared, a_shared);
+
+    // merged entries can be rolled back
+    assert_eq!(a.rollback(checkpoint), [b_only]);
+    assert_eq!(a.payload(&Atom::new("if")), None);
+}
+
+#[test]
+fn canonicalize_atoms() {
+    let shared = "a_name_both_files_use";
+    let mut program = AtomStore::default();
+    let canonical = program.atom(shared);
+
+    let mut file = AtomStore::default();
+    let mut atoms = (
+        vec![file.atom(shared), file.atom("if")],
+        Some(file.intern::<Bits32, _>(shared)),
+        Box::new(file.atom("a_name_only_this_file_uses")),
+    );
+    assert_ne!(atoms.0[0].inner, canonical.inner);
+
+    let mut child = AtomStore::with_parent(&program);
+    assert_eq!(child.canonicalize(&atoms.0[0]).inner, canonical.inner);
+    drop(child);
+
+    program.canonicalize_all(&mut atoms);
+    drop(file);
+    assert_eq!(atoms.0[0].inner, canonical.inner);
+    assert_eq!(atoms.0[1], "if");
+    assert_eq!(
+        atoms.1.as_ref().unwrap().inner,
+        canonical.clone().resize::<Bits32>().inner
+    );
+    assert_eq!(
+        program.get("a_name_only_this_file_uses").unwrap().inner,
+        atoms.2.inner,
+        "the store takes over atoms it didn't have"
+    );
+
+    // std collections
+    let mut file = AtomStore::default();
+    let mut map = std::collections::HashMap::from([("shared", file.atom(shared))]);
+    let mut set = std::collections::HashSet::from([file.atom(shared)]);
+    let mut tree = std::collections::BTreeMap::from([(0, vec![file.atom(shared)])]);
+    program.canonicalize_all(&mut (&mut map, &mut set, &mut tree));
+    assert_eq!(map["shared"].inner, canonical.inner);
+    assert_eq!(set.iter().next().unwrap().inner, canonical.inner);
+    assert_eq!(tree[&0][0].inner, canonical.inner);
+}
+
+#[test]
+fn iterate_and_retain() {
+    let mut store: AtomStore<u8> = AtomStore::new();
+    assert!(store.is_empty());
+    let names = [
+        "zz_added_first_but_sorts_last",
+        "if",
+        "aa_added_last_but_sorts_first",
+    ];
+    for (i, name) in (0..).zip(names) {
+        drop(store.intern_with(name, || i));
+    }
+    assert_eq!(store.len(), 3);
+    assert_eq!(store.iter().collect::<Vec<_>>(), names);
+    assert_eq!(
+        store.dump(),
+        "\"aa_added_last_but_sorts_first\"\n\"if\"\n\"zz_added_first_but_sorts_last\"\n"
+    );
+
+    let kept = store.atom("kept_while_an_atom_points_to_it");
+    store.retain(|s| s.starts_with("zz"));
+    assert_eq!(store.iter().collect::<Vec<_>>(), [names[0], kept.as_str()]);
+    assert_eq!(store.payload(&Atom::new(names[0])), Some(&0));
+
+    store.reserve(100);
+    store.clear();
+    store.shrink_to_fit();
+    assert_eq!(store.iter().collect::<Vec<_>>(), [kept.clone()]);
+    drop(kept);
+    store.clear();
+    assert!(store.is_empty());
+    assert_eq!(store.dump(), "");
+}
+
+#[test]
+fn store_limits() {
+    let long = |i: usize| format!("a_long_identifier_number_{i:04}");
+
+    let mut store: AtomStore<u8> = AtomStore::builder()
+        .capacity(4)
+        .max_entries(3)
+        .max_bytes(long(0).len() + 5)
+        .build();
+    store.intern_with("if", || 1);
+    store.atom(long(0));
+    assert_eq!(
+        store.try_atom(long(1)),
+        Err(StoreError::TooManyBytes {
+            limit: long(0).len() + 5
+        })
+    );
+    assert!(store.try_intern_with("for", || 2).is_ok());
+    assert_eq!(
+        store.try_intern::<Bits64, _>(long(2)),
+        Err(StoreError::TooManyEntries { limit: 3 })
+    );
+    // strings it already has, and inline strings, can still be interned
+    assert_eq!(store.try_atom(long(0)).unwrap(), *long(0));
+    assert_eq!(store.try_atom("do").unwrap(), "do");
+
+    // removing entries makes room again
+    store.retain(|s| s.len() <= MAX_INLINE_LEN);
+    assert!(store.try_atom(long(1)).is_ok());
+
+    let mut short: AtomStore = AtomStore::builder().max_len(3).build();
+    assert_eq!(
+        short.try_atom("else"),
+        Err(StoreError::TooLong { len: 4, limit: 3 })
+    );
+    assert!(short.try_atom("if").is_ok());
+}
+
+#[test]
+#[should_panic = "atom store is full: it can hold at most 0 entries"]
+fn store_limit_panics() {
+    let mut store: AtomStore = AtomStore::builder().max_entries(0).build();
+    store.atom("a_string_that_needs_an_entry_in_the_store");
+}
+
+#[test]
+fn anonymous_stores() {
+    let text = "a_string_in_two_anonymous_stores";
+    let anonymous = AtomStore::builder().id_policy(IdPolicy::Anonymous);
+    let mut first: AtomStore = anonymous.build();
+    let mut second: AtomStore = anonymous.build();
+    let atom = first.atom(text);
+    let other = second.atom(text);
+    assert_ne!(atom.inner, other.inner);
+    assert_eq!(atom, other);
+    assert_eq!(atom, first.atom(text));
+    assert_ne!(atom, first.atom("another_string_in_an_anonymous_store"));
+}
+
+#[test]
+fn store_id_exhaustion() {
+    static IDS: StoreIds = StoreIds::new(2);
+    let text = "a_string_that_needs_an_entry_in_the_store";
+    let builder = AtomStore::builder().ids(&IDS);
+
+    let mut first: AtomStore = builder.build();
+    let mut second: AtomStore = builder.build();
+    assert!(matches!(
+        builder.try_build::<()>(),
+        Err(StoreError::OutOfIds)
+    ));
+    let anonymous: AtomStore = builder.id_policy(IdPolicy::Anonymous).build();
+    drop(anonymous);
+
+    // a store whose atoms are all gone gives its ID back
+    let first_id = first.id;
+    drop(first.atom(text));
+    drop(first);
+    let mut third: AtomStore = builder.build();
+    assert_eq!(third.id, first_id);
+
+    // a store whose atoms are still alive doesn't, so the ID can't be given
+    // to a store that would make a second allocation for the same string
+    let kept = second.atom(text);
+    drop(second);
+    assert!(builder.try_build::<()>().is_err());
+    assert_eq!(third.atom(text), kept);
+
+    // nor does a store that was merged into another one
+    let mut fourth: AtomStore = builder.id_policy(IdPolicy::Anonymous).build();
+    fourth.merge(third);
+    assert!(builder.try_build::<()>().is_err());
+    drop(fourth);
+    assert!(builder.try_build::<()>().is_err());
+}
+
+#[test]
+fn frozen_store_ids() {
+    static IDS: StoreIds = StoreIds::new(1);
+    let builder = AtomStore::builder().ids(&IDS);
+
+    let mut store: AtomStore = builder.build();
+    store.atom("a_string_that_needs_an_entry_in_the_store");
+    let frozen = store.freeze().with_perfect_hash();
+    assert!(builder.try_build::<()>().is_err());
+    drop(frozen);
+    assert!(builder.try_build::<()>().is_ok());
+}
+
+#[test]
+fn shared_store() {
+    let names: Vec<String> = (0..64)
+        .map(|i| format!("a_shared_identifier_{i:03}"))
+        .collect();
+    let store = SharedAtomStore::with_shards(3);
+    assert_eq!(store.shards.len(), 4);
+
+    let per_thread: Vec<Vec<Atom<'static>>> = std::thread::scope(|scope| {
+        let threads: Vec<_> = (0..4)
+            .map(|_| scope.spawn(|| names.iter().map(|name| store.atom(name)).collect()))
+            .collect();
+        threads.into_iter().map(|t| t.join().unwrap()).collect()
+    });
+    assert_eq!(store.len(), names.len());
+    for atoms in &per_thread[1..] {
+        for (atom, first) in atoms.iter().zip(&per_thread[0]) {
+            assert_eq!(atom.inner, first.inner);
+        }
+    }
+    assert_eq!(store.get(&names[0]).unwrap().inner, per_thread[0][0].inner);
+    assert!(store.get("not_in_the_shared_store").is_none());
+    assert_eq!(
+        store.intern::<Bits32, _>(&names[1]).inner,
+        per_thread[0][1].clone().resize::<Bits32>().inner
+    );
+}
+
+#[test]
+fn shared_store_ids() {
+    static IDS: StoreIds = StoreIds::new(1);
+    let text = "a_string_that_needs_an_entry_in_the_store";
+    let builder = AtomStore::builder().ids(&IDS);
+
+    // the shards share one ID and one arena
+    let store = SharedAtomStore::from_first_shard(builder.build(), 16);
+    let first = store.shards[0].lock().unwrap();
+    for shard in &store.shards[1..] {
+        let shard = shard.lock().unwrap();
+        assert_eq!(shard.id, first.id);
+        assert!(alloc::sync::Arc::ptr_eq(&shard.arena, &first.arena));
+    }
+    drop(first);
+    assert!(builder.try_build::<()>().is_err());
+
+    // and give it back once, when none of their atoms are alive
+    drop(store.atom(text));
+    drop(store);
+    let store = SharedAtomStore::from_first_shard(builder.build(), 16);
+    let kept = store.atom(text);
+    drop(store);
+    assert!(builder.try_build::<()>().is_err());
+    assert_eq!(kept, text);
+}
+
+#[test]
+fn atom_cache() {
+    let store = SharedAtomStore::new();
+    let mut cache = store.cache(3);
+    assert_eq!(cache.slots.len(), 4);
+    assert!(cache.stats().hit_rate().abs() < f64::EPSILON);
+
+    let name = "an_identifier_used_over_and_over";
+    let first = cache.atom(name);
+    for _ in 0..3 {
+        assert_eq!(cache.atom(name).inner, first.inner);
+    }
+    assert_eq!(cache.atom("if"), "if");
+    assert_eq!(cache.stats(), CacheStats { hits: 3, misses: 1 });
+    assert!((cache.stats().hit_rate() - 0.75).abs() < f64::EPSILON);
+
+    // every string still gets the store's atom, whatever its slot held
+    let others: Vec<String> = (0..32)
+        .map(|i| format!("another_identifier_{i:03}"))
+        .collect();
+    for other in &others {
+        assert_eq!(cache.atom(other).inner, store.atom(other).inner);
+    }
+    assert_eq!(cache.atom(name).inner, first.inner);
+
+    cache.flush();
+    assert!(cache.slots.iter().all(Option::is_none));
+    let misses = cache.stats().misses;
+    assert_eq!(cache.atom(name).inner, first.inner);
+    assert_eq!(cache.stats().misses, misses + 1);
+    assert_eq!(cache.store().len(), 33);
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn intern_par_iter() {
+    use rayon::prelude::*;
+
+    let owned: Vec<String> = (0..500)
+        .map(|i| {
+            if i % 3 == 0 {
+                format!("x{}", i % 7)
+            } else {
+                format!("a_symbol_from_the_index_{}", i % 200)
+            }
+        })
+        .collect();
+    let names: Vec<&str> = owned.iter().map(String::as_str).collect();
+    let strings = || names.par_iter().copied();
+
+    let mut store = AtomStore::default();
+    let atoms = store.intern_par_iter(strings());
+    assert_eq!(atoms, names);
+    for (atom, name) in atoms.iter().zip(&names) {
+        assert_eq!(atom.inner, store.atom(name).inner);
+    }
+
+    let shared = SharedAtomStore::with_shards(4);
+    let atoms = shared.intern_par_iter(strings());
+    assert_eq!(atoms, names);
+    assert_eq!(shared.len(), store.len());
+    for (atom, name) in atoms.iter().zip(&names) {
+        assert_eq!(atom.inner, shared.atom(name).inner);
+    }
+    assert!(shared.intern_par_iter(rayon::iter::empty()).is_empty());
+}
+
+#[test]
+fn intern_batch() {
+    let owned: Vec<String> = (0..300)
+        .map(|i| {
+            if i % 4 == 0 {
+                format!("y{}", i % 5)
+            } else {
+                format!("an_identifier_from_the_cache_{}", i % 100)
+            }
+        })
+        .collect();
+    let names: Vec<&str> = owned.iter().map(String::as_str).collect();
+
+    let mut store = AtomStore::default();
+    let atoms = store.intern_batch(&names);
+    assert_eq!(atoms, names);
+    assert_eq!(store.len(), 75);
+    for (atom, name) in atoms.iter().zip(&names) {
+        assert_eq!(atom.inner, store.atom(name).inner);
+    }
+    // a second batch finds the same entries
+    let again = store.intern_batch(&names);
+    assert!(again.iter().zip(&atoms).all(|(a, b)| a.inner == b.inner));
+    assert!(store.intern_batch(&[]).is_empty());
+
+    let mut extended = AtomStore::default();
+    extended.extend(&owned);
+    extended.extend(["another_identifier_from_the_cache"]);
+    assert_eq!(extended.len(), 76);
+    assert_eq!(extended.dump().lines().count(), 76);
+
+    // strings in a parent aren't added again
+    let mut child = AtomStore::with_parent(&store);
+    let atoms = child.intern_batch(&names);
+    assert!(child.is_empty());
+    assert!(atoms.iter().zip(&again).all(|(a, b)| a.inner == b.inner));
+}
+
+#[test]
+#[should_panic(expected = "at most 2 entries")]
+fn intern_batch_limits() {
+    let mut store: AtomStore = AtomStore::builder().max_entries(2).build();
+    store.intern_batch(&[
+        "the_first_long_identifier",
+        "the_second_long_identifier",
+        "the_third_long_identifier",
+    ]);
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+#[should_panic(expected = "longer than the atom store accepts")]
+fn intern_par_iter_max_len() {
+    use rayon::prelude::*;
+
+    let mut store: AtomStore = AtomStore::builder().max_len(2).build();
+    store.intern_par_iter(["ab", "abc"].into_par_iter());
+}
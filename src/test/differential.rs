@@ -0,0 +1,70 @@
+//! Differential tests checking [`Atom`] against plain [`String`] semantics.
+
+use proptest::prelude::*;
+
+use crate::model::{check, Model, Op, STORES};
+use crate::{Atom, AtomStore};
+
+/// Strings biased towards collisions and the inline/heap boundary.
+fn text() -> impl Strategy<Value = String> {
+    prop_oneof![
+        "[ab]{0,3}",
+        "[ab]{6,10}",
+        "[ab]{14,17}",
+        "[a-zA-Z0-9_$]{0,40}",
+        "\\PC{0,24}",
+    ]
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        text().prop_map(Op::New),
+        (0..STORES, text()).prop_map(|(store, s)| Op::Intern(store, s)),
+        any::<usize>().prop_map(Op::Clone),
+        any::<usize>().prop_map(Op::Drop),
+        (0..STORES).prop_map(Op::DropStore),
+    ]
+}
+
+fn config() -> ProptestConfig {
+    if cfg!(miri) {
+        ProptestConfig {
+            cases: 4,
+            failure_persistence: None,
+            ..ProptestConfig::default()
+        }
+    } else {
+        ProptestConfig::default()
+    }
+}
+
+proptest! {
+    #![proptest_config(config())]
+
+    #[test]
+    fn single_atom(s in text()) {
+        let atom = Atom::new(&s);
+        let clone = atom.clone();
+        check(&[(atom, s.clone()), (clone, s)]);
+    }
+
+    #[test]
+    fn pair_of_atoms(s in text(), t in text()) {
+        let mut store = AtomStore::default();
+        check(&[
+            (Atom::new(&s), s.clone()),
+            (store.atom(&s), s.clone()),
+            (Atom::new(&t), t.clone()),
+            (store.atom(&t), t),
+        ]);
+    }
+
+    #[test]
+    fn operation_sequence(ops in prop::collection::vec(op(), 1..48)) {
+        let mut model = Model::default();
+        for op in ops {
+            model.apply(op);
+            model.check();
+        }
+    }
+}
@@ -0,0 +1,178 @@
+//! Walking every atom in a data structure, used by
+//! [`AtomStore::canonicalize_all`](crate::AtomStore::canonicalize_all).
+
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
+use alloc::vec::Vec;
+use core::hash::{BuildHasher, Hash};
+use std::collections::{HashMap, HashSet};
+
+use crate::size::AtomSize;
+use crate::Atom;
+
+/// Something that can look at, and replace, atoms one at a time.
+///
+/// [`AtomStore`](crate::AtomStore) is a visitor that swaps each atom for its
+/// own atom for the same string.
+pub trait AtomVisitor {
+    fn visit<S: AtomSize>(&mut self, atom: &mut Atom<'_, S>);
+}
+
+/// A data structure holding atoms.
+///
+/// Implement this for your own types by passing each field that holds atoms
+/// to the visitor:
+///
+/// ```
+/// use stern::{Atom, AtomVisitor, VisitAtoms};
+///
+/// struct Function {
+///     name: Atom<'static>,
+///     params: Vec<Atom<'static>>,
+/// }
+///
+/// impl VisitAtoms for Function {
+///     fn visit_atoms<V: AtomVisitor>(&mut self, visitor: &mut V) {
+///         self.name.visit_atoms(visitor);
+///         self.params.visit_atoms(visitor);
+///     }
+/// }
+/// ```
+pub trait VisitAtoms {
+    /// Pass every atom in `self` to `visitor`.
+    fn visit_atoms<V: AtomVisitor>(&mut self, visitor: &mut V);
+}
+
+impl<S: AtomSize> VisitAtoms for Atom<'_, S> {
+    #[inline]
+    fn visit_atoms<V: AtomVisitor>(&mut self, visitor: &mut V) {
+        visitor.visit(self);
+    }
+}
+
+impl<T: VisitAtoms> VisitAtoms for [T] {
+    fn visit_atoms<V: AtomVisitor>(&mut self, visitor: &mut V) {
+        for item in self {
+            item.visit_atoms(visitor);
+        }
+    }
+}
+
+impl<T: VisitAtoms, const N: usize> VisitAtoms for [T; N] {
+    fn visit_atoms<V: AtomVisitor>(&mut self, visitor: &mut V) {
+        self.as_mut_slice().visit_atoms(visitor);
+    }
+}
+
+impl<T: VisitAtoms> VisitAtoms for Vec<T> {
+    fn visit_atoms<V: AtomVisitor>(&mut self, visitor: &mut V) {
+        self.as_mut_slice().visit_atoms(visitor);
+    }
+}
+
+impl<T: VisitAtoms> VisitAtoms for VecDeque<T> {
+    fn visit_atoms<V: AtomVisitor>(&mut self, visitor: &mut V) {
+        for item in self {
+            item.visit_atoms(visitor);
+        }
+    }
+}
+
+impl<T: VisitAtoms + ?Sized> VisitAtoms for Box<T> {
+    fn visit_atoms<V: AtomVisitor>(&mut self, visitor: &mut V) {
+        (**self).visit_atoms(visitor);
+    }
+}
+
+impl<T: VisitAtoms + ?Sized> VisitAtoms for &mut T {
+    fn visit_atoms<V: AtomVisitor>(&mut self, visitor: &mut V) {
+        (**self).visit_atoms(visitor);
+    }
+}
+
+impl<T: VisitAtoms> VisitAtoms for Option<T> {
+    fn visit_atoms<V: AtomVisitor>(&mut self, visitor: &mut V) {
+        if let Some(value) = self {
+            value.visit_atoms(visitor);
+        }
+    }
+}
+
+impl<K, T: VisitAtoms, H> VisitAtoms for hashbrown::HashMap<K, T, H> {
+    /// Only visits the values. Keys can't be changed in place.
+    fn visit_atoms<V: AtomVisitor>(&mut self, visitor: &mut V) {
+        for value in self.values_mut() {
+            value.visit_atoms(visitor);
+        }
+    }
+}
+
+impl<K, T: VisitAtoms, H> VisitAtoms for HashMap<K, T, H> {
+    /// Only visits the values. Keys can't be changed in place.
+    fn visit_atoms<V: AtomVisitor>(&mut self, visitor: &mut V) {
+        for value in self.values_mut() {
+            value.visit_atoms(visitor);
+        }
+    }
+}
+
+impl<K, T: VisitAtoms> VisitAtoms for BTreeMap<K, T> {
+    /// Only visits the values. Keys can't be changed in place.
+    fn visit_atoms<V: AtomVisitor>(&mut self, visitor: &mut V) {
+        for value in self.values_mut() {
+            value.visit_atoms(visitor);
+        }
+    }
+}
+
+impl<T: VisitAtoms + Eq + Hash, H: BuildHasher> VisitAtoms for hashbrown::HashSet<T, H> {
+    /// Takes every item out of the set and puts it back, since items can't
+    /// be changed in place. Visiting mustn't change how items compare.
+    fn visit_atoms<V: AtomVisitor>(&mut self, visitor: &mut V) {
+        let items: Vec<T> = self.drain().collect();
+        for mut item in items {
+            item.visit_atoms(visitor);
+            self.insert(item);
+        }
+    }
+}
+
+impl<T: VisitAtoms + Eq + Hash, H: BuildHasher> VisitAtoms for HashSet<T, H> {
+    /// Takes every item out of the set and puts it back, since items can't
+    /// be changed in place. Visiting mustn't change how items compare.
+    fn visit_atoms<V: AtomVisitor>(&mut self, visitor: &mut V) {
+        let items: Vec<T> = self.drain().collect();
+        for mut item in items {
+            item.visit_atoms(visitor);
+            self.insert(item);
+        }
+    }
+}
+
+impl<T: VisitAtoms + Ord> VisitAtoms for BTreeSet<T> {
+    /// Takes every item out of the set and puts it back, since items can't
+    /// be changed in place. Visiting mustn't change how items compare.
+    fn visit_atoms<V: AtomVisitor>(&mut self, visitor: &mut V) {
+        for mut item in core::mem::take(self) {
+            item.visit_atoms(visitor);
+            self.insert(item);
+        }
+    }
+}
+
+macro_rules! impl_tuple {
+    ($($name:ident),+) => {
+        impl<$($name: VisitAtoms),+> VisitAtoms for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn visit_atoms<V: AtomVisitor>(&mut self, visitor: &mut V) {
+                let ($($name,)+) = self;
+                $($name.visit_atoms(visitor);)+
+            }
+        }
+    };
+}
+
+impl_tuple!(A);
+impl_tuple!(A, B);
+impl_tuple!(A, B, C);
+impl_tuple!(A, B, C, D);